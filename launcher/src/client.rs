@@ -1,12 +1,21 @@
 use anyhow::{anyhow, Result};
 
 use launcher_api::message::{
-    AuthMessage, AuthResponse, ClientMessage, JoinServerMessage, ProfileMessage, ProfileResponse,
-    ProfilesInfoMessage, ProfilesInfoResponse, ServerMessage,
+    AuthMessage, AuthResponse, ClientMessage, ClientRequest, HandshakeMessage, JoinServerMessage,
+    LogoutMessage, ProfileMessage, ProfileResponse, ProfilesInfoMessage, ProfilesInfoResponse,
+    RefreshMessage, ServerMessage, ServerResponse, PROTOCOL_VERSION,
 };
-use launcher_api::message::{Error, ProfileResourcesMessage, ProfileResourcesResponse};
+use launcher_api::message::{Error, ErrorKind, ProfileResourcesMessage, ProfileResourcesResponse};
+use launcher_api::validation::HashAlgorithm;
+use log::debug;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
 
 use crate::config::CONFIG;
 
@@ -17,11 +26,20 @@ use uuid::Uuid;
 
 pub mod downloader;
 
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const RESOURCES_TIMEOUT: Duration = Duration::from_secs(120);
+
+type PendingRequests = Arc<Mutex<HashMap<u64, oneshot::Sender<ServerMessage>>>>;
+
 pub struct Client {
     out: Sender<String>,
-    recv: Receiver<String>,
+    pending: PendingRequests,
+    next_id: AtomicU64,
     security: SecurityManager,
-    pub auth_info: Option<AuthInfo>,
+    // Separate lock, requests must not wait for each other
+    auth_info: Mutex<Option<AuthInfo>>,
+    // Chosen by server in handshake, None for servers without digests
+    hash_algorithm: Option<HashAlgorithm>,
 }
 
 #[derive(Clone)]
@@ -35,12 +53,58 @@ impl Client {
     pub async fn new() -> Result<Self> {
         let address: &str = &CONFIG.websocket;
         let (s, r) = Client::connect(&address).await?;
-        Ok(Client {
+        let pending = PendingRequests::default();
+        tokio::spawn(Client::receive_loop(r, pending.clone()));
+        let mut client = Client {
             security: security::get_manager(),
-            recv: r,
+            pending,
+            next_id: AtomicU64::new(1),
             out: s,
-            auth_info: None,
-        })
+            auth_info: Mutex::new(None),
+            hash_algorithm: None,
+        };
+        client.hash_algorithm = client.handshake().await?;
+        Ok(client)
+    }
+
+    async fn receive_loop(mut recv: Receiver<String>, pending: PendingRequests) {
+        while let Some(text) = recv.recv().await {
+            let (id, message) = match serde_json::from_str::<ServerResponse>(&text) {
+                Ok(response) => (response.id, response.message),
+                Err(_) => {
+                    let id = serde_json::from_str::<Value>(&text)
+                        .ok()
+                        .and_then(|value| value.get("id").and_then(Value::as_u64))
+                        .unwrap_or_default();
+                    let error = ServerMessage::Error(Error::new(
+                        ErrorKind::OutdatedLauncher,
+                        "Unknown server message, please update your launcher!",
+                    ));
+                    (id, error)
+                }
+            };
+            match pending.lock().unwrap().remove(&id) {
+                Some(sender) => {
+                    let _ = sender.send(message);
+                }
+                None => debug!("Response for unknown request {}", id),
+            }
+        }
+        pending.lock().unwrap().clear();
+    }
+
+    async fn handshake(&self) -> Result<Option<HashAlgorithm>> {
+        let message = ClientMessage::Handshake(HandshakeMessage {
+            protocol_version: PROTOCOL_VERSION,
+            launcher_version: env!("CARGO_PKG_VERSION").to_string(),
+            os_type: get_os_type()?,
+            hash_algorithms: vec![HashAlgorithm::Sha256],
+        });
+        match self.send_sync(message).await {
+            ServerMessage::Handshake(response) => Ok(response.hash_algorithm),
+            ServerMessage::Error(error) => Err(error.into()),
+            _ => Err(anyhow::anyhow!("Handshake error")),
+        }
     }
 
     async fn connect(address: &str) -> Result<(Sender<String>, Receiver<String>)> {
@@ -52,23 +116,59 @@ impl Client {
         Ok(ws.into_channel().await)
     }
 
+    pub fn get_auth_info(&self) -> Option<AuthInfo> {
+        self.auth_info.lock().unwrap().clone()
+    }
+
+    pub fn set_auth_info(&self, auth_info: Option<AuthInfo>) -> Option<AuthInfo> {
+        std::mem::replace(&mut *self.auth_info.lock().unwrap(), auth_info)
+    }
+
     pub async fn get_encrypted_password(&self, password: &str) -> String {
         self.security.encrypt(password)
     }
 
-    pub async fn auth(&mut self, login: &str, password: &str) -> Result<AuthResponse> {
+    pub async fn auth(
+        &self,
+        login: &str,
+        password: &str,
+        remember_me: bool,
+    ) -> Result<AuthResponse> {
         let message = ClientMessage::Auth(AuthMessage {
             login: String::from(login),
             password: password.to_string(),
+            remember_me,
         });
         match self.send_sync(message).await {
             ServerMessage::Auth(auth) => Ok(auth),
-            ServerMessage::Error(error) => Err(anyhow::anyhow!("{}", error.msg)),
+            ServerMessage::Error(error) => Err(error.into()),
             _ => Err(anyhow::anyhow!("Auth not found")),
         }
     }
 
-    pub async fn join(&mut self, token: &str, profile: &Uuid, server: &str) -> Result<()> {
+    pub async fn refresh(&self, refresh_token: &str) -> Result<AuthResponse> {
+        let message = ClientMessage::Refresh(RefreshMessage {
+            refresh_token: String::from(refresh_token),
+        });
+        match self.send_sync(message).await {
+            ServerMessage::Auth(auth) => Ok(auth),
+            ServerMessage::Error(error) => Err(error.into()),
+            _ => Err(anyhow::anyhow!("Auth not found")),
+        }
+    }
+
+    pub async fn logout(&self, access_token: &str) -> Result<()> {
+        let message = ClientMessage::Logout(LogoutMessage {
+            access_token: String::from(access_token),
+        });
+        match self.send_sync(message).await {
+            ServerMessage::Empty => Ok(()),
+            ServerMessage::Error(error) => Err(error.into()),
+            _ => Err(anyhow::anyhow!("Logout error")),
+        }
+    }
+
+    pub async fn join(&self, token: &str, profile: &Uuid, server: &str) -> Result<()> {
         let message = ClientMessage::JoinServer(JoinServerMessage {
             access_token: String::from(token),
             selected_profile: *profile,
@@ -76,53 +176,94 @@ impl Client {
         });
         match self.send_sync(message).await {
             ServerMessage::Empty => Ok(()),
-            ServerMessage::Error(error) => Err(anyhow::anyhow!("{}", error.msg)),
+            ServerMessage::Error(error) => Err(error.into()),
             _ => Err(anyhow::anyhow!("Auth not found")),
         }
     }
 
-    pub async fn get_resources(&mut self, profile: &str) -> Result<ProfileResourcesResponse> {
+    pub fn hash_algorithm(&self) -> Option<HashAlgorithm> {
+        self.hash_algorithm
+    }
+
+    pub async fn get_resources(&self, profile: &str) -> Result<ProfileResourcesResponse> {
+        let os_type = get_os_type()?;
         let message = ClientMessage::ProfileResources(ProfileResourcesMessage {
             profile: String::from(profile),
-            os_type: get_os_type(),
+            os_type: os_type.clone(),
         });
-        match self.send_sync(message).await {
-            ServerMessage::ProfileResources(profile) => Ok(profile),
-            ServerMessage::Error(error) => Err(anyhow::anyhow!("{}", error.msg)),
+        match self.send(message, RESOURCES_TIMEOUT).await {
+            ServerMessage::ProfileResources(resources) => {
+                let resources = self.security.verify(&resources)?;
+                if resources.profile_name != profile || resources.os_type != os_type {
+                    return Err(anyhow::anyhow!(
+                        "Profile resources were signed for another profile"
+                    ));
+                }
+                Ok(resources)
+            }
+            ServerMessage::Error(error) => Err(error.into()),
             _ => Err(anyhow::anyhow!("Profile resources sync error")),
         }
     }
 
-    pub async fn get_profiles(&mut self) -> Result<ProfilesInfoResponse> {
+    pub async fn get_profiles(&self) -> Result<ProfilesInfoResponse> {
         let message = ClientMessage::ProfilesInfo(ProfilesInfoMessage);
         match self.send_sync(message).await {
             ServerMessage::ProfilesInfo(info) => Ok(info),
-            ServerMessage::Error(error) => Err(anyhow::anyhow!("{}", error.msg)),
+            ServerMessage::Error(error) => Err(error.into()),
             _ => Err(anyhow::anyhow!("Profiles info sync error")),
         }
     }
 
-    pub async fn get_profile(&mut self, profile: &str) -> Result<ProfileResponse> {
+    pub async fn get_profile(&self, profile: &str) -> Result<ProfileResponse> {
         let message = ClientMessage::Profile(ProfileMessage {
             profile: String::from(profile),
         });
         match self.send_sync(message).await {
-            ServerMessage::Profile(profile) => Ok(profile),
-            ServerMessage::Error(error) => Err(anyhow::anyhow!("{}", error.msg)),
+            ServerMessage::Profile(response) => {
+                let response = self.security.verify(&response)?;
+                if response.profile.name != profile {
+                    return Err(anyhow::anyhow!("Profile was signed for another profile"));
+                }
+                Ok(response)
+            }
+            ServerMessage::Error(error) => Err(error.into()),
             _ => Err(anyhow::anyhow!("Profile sync error!")),
         }
     }
 
-    async fn send_sync(&mut self, msg: ClientMessage) -> ServerMessage {
-        self.out
-            .send(serde_json::to_string(&msg).unwrap())
+    async fn send_sync(&self, msg: ClientMessage) -> ServerMessage {
+        self.send(msg, REQUEST_TIMEOUT).await
+    }
+
+    async fn send(&self, msg: ClientMessage, timeout: Duration) -> ServerMessage {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        let request = ClientRequest { id, message: msg };
+        if self
+            .out
+            .clone()
+            .send(serde_json::to_string(&request).unwrap())
             .await
-            .expect("Can't send message to server");
-        match self.recv.recv().await {
-            Some(message) => serde_json::from_str(&message).unwrap(),
-            None => ServerMessage::Error(Error {
-                msg: "Server Disconnected".to_string(),
-            }),
+            .is_err()
+        {
+            self.pending.lock().unwrap().remove(&id);
+            return ServerMessage::Error(Error::new(
+                ErrorKind::ServerDisconnected,
+                "Server Disconnected",
+            ));
+        }
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(message)) => message,
+            Ok(Err(_)) => ServerMessage::Error(Error::new(
+                ErrorKind::ServerDisconnected,
+                "Server Disconnected",
+            )),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                ServerMessage::Error(Error::new(ErrorKind::Timeout, "Server response timed out"))
+            }
         }
     }
 }
@@ -5,7 +5,7 @@ use hyper::{Body, Client, Request, Uri};
 use hyper_tls::HttpsConnector;
 
 use futures::Future;
-use launcher_api::validation::RemoteFile;
+use launcher_api::validation::{RelativePath, RemoteFile};
 use std::fs;
 use std::io::SeekFrom;
 use std::path::Path;
@@ -19,10 +19,13 @@ use web_view::Handle;
 const SMALL_SIZE: u64 = 1048576;
 const CHUNK_SIZE: u64 = 512000;
 
-pub async fn download(files: Vec<(String, RemoteFile)>, handler: Handle<()>) -> Result<()> {
+pub async fn download(files: Vec<(RelativePath, RemoteFile)>, handler: Handle<()>) -> Result<()> {
     let (progress_sender, mut receiver) = mpsc::unbounded_channel::<u64>();
     let total_size = files.iter().map(|file| file.1.size).sum::<u64>();
-    type Download = (Vec<(String, RemoteFile)>, Vec<(String, RemoteFile)>);
+    type Download = (
+        Vec<(RelativePath, RemoteFile)>,
+        Vec<(RelativePath, RemoteFile)>,
+    );
     let (concurrent, single): Download = files
         .into_iter()
         .partition(|file| file.1.size <= SMALL_SIZE);
@@ -110,12 +113,12 @@ fn get_chunks(file_size: u64) -> Vec<(u64, u64)> {
 
 pub async fn concurrent_download(
     remote_file: RemoteFile,
-    path: String,
+    path: RelativePath,
     progress_sender: UnboundedSender<u64>,
 ) -> Result<()> {
     let (sender, mut receiver) = mpsc::unbounded_channel();
     let total_size = remote_file.size;
-    let mut file = create_file(Path::new(&path)).await?;
+    let mut file = create_file(&path.to_path()).await?;
     let uri: Uri = remote_file.uri.parse()?;
     let mut tasks = get_chunks(remote_file.size - 1)
         .into_iter()
@@ -169,11 +172,11 @@ pub async fn concurrent_download(
 
 async fn single_thread_download(
     remote_file: RemoteFile,
-    path: String,
+    path: RelativePath,
     progress_sender: UnboundedSender<u64>,
 ) -> Result<()> {
     tokio::spawn(async move {
-        let mut file = create_file(Path::new(&path)).await?;
+        let mut file = create_file(&path.to_path()).await?;
         let client = Client::builder().build::<_, hyper::Body>(HttpsConnector::new());
         let mut resp = client.get(remote_file.uri.parse()?).await?;
         if resp.status().is_success() {
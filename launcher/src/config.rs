@@ -19,6 +19,8 @@ pub struct Config {
     pub websocket: String,
     pub ram: u64,
     pub project_name: String,
+    #[serde(default)]
+    pub features: Vec<String>,
 }
 
 impl Configurable for Config {}
@@ -37,7 +39,7 @@ pub struct Settings {
     pub game_dir: String,
     pub save_data: bool,
     pub ram: u64,
-    pub saved_password: Option<String>,
+    pub refresh_token: Option<String>,
     pub last_name: Option<String>,
 }
 
@@ -68,7 +70,7 @@ impl Default for Settings {
             game_dir: CONFIG.game_dir.clone(),
             save_data: false,
             ram: CONFIG.ram,
-            saved_password: None,
+            refresh_token: None,
             last_name: None,
         }
     }
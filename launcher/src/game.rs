@@ -9,6 +9,7 @@ use std::path::{Path, PathBuf};
 
 use crate::client::AuthInfo;
 use crate::game::auth::Java_com_mojang_authlib_yggdrasil_launcherJoinRequest;
+use crate::security::validation::get_os_type;
 use std::os::raw::c_void;
 
 pub(crate) mod auth;
@@ -20,19 +21,24 @@ const JVM_LIB_PATH: &str = "bin/server/jvm.dll";
 const JVM_LIB_PATH: &str = "lib/amd64/server/libjvm.so";
 #[cfg(all(target_os = "linux", target_arch = "x86"))]
 const JVM_LIB_PATH: &str = "lib/i386/server/libjvm.so";
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+const JVM_LIB_PATH: &str = "lib/aarch64/server/libjvm.so";
 #[cfg(target_os = "macos")]
 const JVM_LIB_PATH: &str = "lib/server/libjvm.dylib";
 
 pub fn create_jvm(profile: Profile, dir: &str, ram: u64) -> Result<JavaVM> {
-    let args = InitArgsBuilder::new()
+    let os_type = get_os_type()?;
+    let mut args = InitArgsBuilder::new()
         .option(&format!("-Xmx{}M", ram))
         .option("-Dfml.ignoreInvalidMinecraftCertificates=true")
         .option("-Dfml.ignorePatchDiscrepancies=true")
         .option("-XX:+DisableAttachMechanism")
         .option(&profile.get_native_option(dir))
-        .option(&profile.create_lib_string(dir))
-        .version(JNIVersion::V8)
-        .build();
+        .option(&profile.create_lib_string(dir, &os_type));
+    for arg in profile.create_jvm_args(&os_type) {
+        args = args.option(&arg);
+    }
+    let args = args.version(JNIVersion::V8).build();
 
     if cfg!(windows) {
         let mut bin_path = PathBuf::from(dir);
@@ -59,6 +65,7 @@ pub fn create_jvm(profile: Profile, dir: &str, ram: u64) -> Result<JavaVM> {
 }
 
 pub fn start(jvm: JavaVM, profile: Profile, auth_info: AuthInfo, dir: &str) -> Result<()> {
+    let os_type = get_os_type()?;
     let jni_env = jvm.attach_current_thread_permanently()?;
     let method = NativeMethod {
         name: "launcherJoinRequest".into(),
@@ -74,7 +81,7 @@ pub fn start(jvm: JavaVM, profile: Profile, auth_info: AuthInfo, dir: &str) -> R
             &profile.main_class,
             "main",
             "([Ljava/lang/String;)V",
-            &[profile.create_args(dir, &jni_env, auth_info, &profile)],
+            &[profile.create_args(dir, &jni_env, auth_info, &profile, &os_type)],
         )?
         .v()?;
     Ok(())
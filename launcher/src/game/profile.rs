@@ -1,7 +1,9 @@
 use crate::client::AuthInfo;
+use crate::config::CONFIG;
 use jni::objects::{JObject, JValue};
 use jni::JNIEnv;
-use launcher_api::profile::Profile;
+use launcher_api::profile::{resolve_arguments, Profile};
+use launcher_api::validation::OsType;
 use path_slash::PathExt;
 use std::fs::File;
 use std::path::{Path, PathBuf};
@@ -13,14 +15,17 @@ const CLASS_PATH_SEPARATOR: &str = ";";
 
 pub trait ClientProfile {
     fn new(path: &str) -> Self;
-    fn create_lib_string(&self, dir: &str) -> String;
+    fn create_lib_string(&self, dir: &str, os_type: &OsType) -> String;
     fn get_native_option(&self, dir: &str) -> String;
+    fn create_jvm_args(&self, os_type: &OsType) -> Vec<String>;
+    fn create_client_args(&self, os_type: &OsType) -> Vec<String>;
     fn create_args(
         &self,
         dir: &str,
         env: &JNIEnv,
         auth_info: AuthInfo,
         profile: &Profile,
+        os_type: &OsType,
     ) -> JValue;
     fn get_client_dir(&self, dir: &str) -> PathBuf;
 }
@@ -30,11 +35,16 @@ impl ClientProfile for Profile {
         serde_json::from_reader(File::open(path).unwrap()).unwrap()
     }
 
-    fn create_lib_string(&self, dir: &str) -> String {
+    fn create_lib_string(&self, dir: &str, os_type: &OsType) -> String {
         let mut path = String::from("-Djava.class.path=");
-        for library in &self.libraries {
-            path += &Path::new(&[dir, "/libraries/", library, CLASS_PATH_SEPARATOR].join(""))
-                .to_slash_lossy();
+        for library in self
+            .libraries
+            .iter()
+            .filter(|library| library.is_allowed(os_type, Some(&CONFIG.features)))
+        {
+            path +=
+                &Path::new(&[dir, "/libraries/", library.path(), CLASS_PATH_SEPARATOR].join(""))
+                    .to_slash_lossy();
         }
         let class_path: Vec<_> = self
             .class_path
@@ -56,14 +66,23 @@ impl ClientProfile for Profile {
         )
     }
 
+    fn create_jvm_args(&self, os_type: &OsType) -> Vec<String> {
+        resolve_arguments(&self.jvm_args, os_type, &CONFIG.features)
+    }
+
+    fn create_client_args(&self, os_type: &OsType) -> Vec<String> {
+        resolve_arguments(&self.client_args, os_type, &CONFIG.features)
+    }
+
     fn create_args(
         &self,
         dir: &str,
         env: &JNIEnv,
         auth_info: AuthInfo,
         profile: &Profile,
+        os_type: &OsType,
     ) -> JValue {
-        let mut args = self.client_args.clone();
+        let mut args = self.create_client_args(os_type);
         args.push(String::from("--gameDir"));
         args.push(self.get_client_dir(dir).to_string_lossy().to_string());
         args.push(String::from("--assetsDir"));
@@ -1,19 +1,19 @@
 use crate::client::Client;
 
+use launcher_api::message::{Error, ErrorKind};
 use log::{debug, error};
 use messages::RuntimeMessage;
 use once_cell::sync::OnceCell;
 use std::sync::Arc;
 
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio::sync::Mutex;
 
 use crate::config::CONFIG;
 use web_view::{Content, Error as WVError, Handle, WVResult, WebView};
 
 mod messages;
 
-pub static CLIENT: OnceCell<Arc<Mutex<Client>>> = OnceCell::new();
+pub static CLIENT: OnceCell<Arc<Client>> = OnceCell::new();
 
 pub static PLAYING: OnceCell<()> = OnceCell::new();
 
@@ -22,11 +22,13 @@ macro_rules! handle_error {
     ($handler:expr, $result:expr) => {
         if let Err(error) = $result {
             error!("Runtime message error: {}", error);
+            let kind = $crate::runtime::error_kind(&error);
             $handler
                 .dispatch(move |w| {
                     w.eval(&format!(
-                        r#"app.backend.error("{}")"#,
-                        error.to_string().replace(r#"""#, r#"""#)
+                        r#"app.backend.error("{}", "{:?}")"#,
+                        error.to_string().replace(r#"""#, r#"""#),
+                        kind
                     ))?;
                     Ok(())
                 })
@@ -35,6 +37,13 @@ macro_rules! handle_error {
     };
 }
 
+pub fn error_kind(error: &anyhow::Error) -> ErrorKind {
+    error
+        .downcast_ref::<Error>()
+        .map(|error| error.kind)
+        .unwrap_or(ErrorKind::Internal)
+}
+
 pub async fn start() {
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
     let message_handle = tokio::task::spawn(async move {
@@ -1,18 +1,22 @@
 use crate::client::{AuthInfo, Client};
 use crate::game;
 use crate::game::auth::{CHANNEL_GET, CHANNEL_SEND};
+use crate::runtime;
 use crate::runtime::{CLIENT, PLAYING};
 use crate::security::validation;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use launcher_api::message::AuthResponse;
 use log::error;
 use serde::{Deserialize, Serialize};
 
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use tokio::sync::Mutex;
 use tokio::time::Duration;
 
 use crate::config::{Settings, SETTINGS};
+use launcher_api::validation::RelativePath;
 
 use nfd2::Response;
 use path_slash::PathBufExt;
@@ -41,17 +45,16 @@ pub enum RuntimeMessage {
 }
 
 pub async fn login_user(
-    client: &mut Client,
+    client: &Client,
+    response: AuthResponse,
     login: &str,
-    password: &str,
     handler: Handle<()>,
 ) -> Result<()> {
-    let response = client.auth(login, password).await?;
-    client.auth_info = Some(AuthInfo {
+    client.set_auth_info(Some(AuthInfo {
         access_token: response.access_token,
         uuid: response.uuid,
         username: login.to_string(),
-    });
+    }));
     let profiles = client.get_profiles().await?;
     let json = serde_json::to_string(&profiles.profiles_info)?;
     handler.dispatch(move |w| {
@@ -64,11 +67,26 @@ pub async fn login_user(
     Ok(())
 }
 
+async fn restore_session(settings: &Settings, handler: Handle<()>) -> Result<()> {
+    let login = settings.last_name.as_ref().context("Can't get login")?;
+    let refresh_token = settings
+        .refresh_token
+        .as_ref()
+        .context("Can't get refresh token")?;
+    let client = CLIENT.get().expect("Can't get client");
+    let response = client.refresh(refresh_token).await?;
+    let mut current_settings = SETTINGS.get().expect("Can't take settings").lock().await;
+    current_settings.refresh_token = response.refresh_token.clone();
+    current_settings.save()?;
+    drop(current_settings);
+    login_user(client, response, login, handler).await
+}
+
 pub async fn ready(handler: Handle<()>) -> Result<()> {
     match Client::new().await {
         Ok(c) => {
             CLIENT
-                .set(Arc::new(Mutex::new(c)))
+                .set(Arc::new(c))
                 .map_err(|_| anyhow::anyhow!("Can't update client"))?;
             let settings = match Settings::load() {
                 Ok(s) => s,
@@ -83,10 +101,10 @@ pub async fn ready(handler: Handle<()>) -> Result<()> {
                 .set(Arc::new(Mutex::new(settings.clone())))
                 .expect("Can't update settings");
             if settings.save_data {
-                let login = &settings.last_name.expect("Can't get login");
-                let password = &settings.saved_password.expect("Can't get saved password");
-                let mut client = CLIENT.get().expect("Can't get client").lock().await;
-                login_user(&mut client, login, password, handler.clone()).await?;
+                if let Err(e) = restore_session(&settings, handler.clone()).await {
+                    error!("Can't restore session: {}", e);
+                    clear_saved_data().await?;
+                }
             }
             let mut system = sysinfo::System::new_all();
             system.refresh_all();
@@ -97,10 +115,12 @@ pub async fn ready(handler: Handle<()>) -> Result<()> {
             })?;
         }
         Err(e) => {
+            let kind = runtime::error_kind(&e);
             handler.dispatch(move |w| {
                 w.eval(&format!(
-                    r#"app.backend.error("{}")"#,
-                    e.to_string().replace(r#"""#, r#"""#)
+                    r#"app.backend.error("{}", "{:?}")"#,
+                    e.to_string().replace(r#"""#, r#"""#),
+                    kind
                 ))?;
                 Ok(())
             })?;
@@ -118,51 +138,55 @@ pub async fn login(
     login: String,
     password: String,
     remember: bool,
-    socket: Arc<Mutex<Client>>,
+    client: Arc<Client>,
     handler: Handle<()>,
 ) -> Result<()> {
-    let mut client = socket.lock().await;
     let password = client.get_encrypted_password(&password).await;
-    login_user(&mut client, &login, &password, handler.clone()).await?;
+    let response = client.auth(&login, &password, remember).await?;
+    let refresh_token = response.refresh_token.clone();
+    login_user(&client, response, &login, handler.clone()).await?;
     if remember {
         let mut current_settings = SETTINGS.get().expect("Can't take settings").lock().await;
         current_settings.last_name = Some(login.clone());
-        current_settings.saved_password = Some(password.clone());
+        current_settings.refresh_token = refresh_token;
         current_settings.save_data = true;
         current_settings.save()?;
     }
     Ok(())
 }
 
-pub async fn logout(client: Arc<Mutex<Client>>) -> Result<()> {
-    let mut client = client.lock().await;
-    client.auth_info = None;
+async fn clear_saved_data() -> Result<()> {
     let mut current_settings = SETTINGS.get().expect("Can't take settings").lock().await;
     current_settings.save_data = false;
     current_settings.last_name = None;
-    current_settings.saved_password = None;
-    current_settings.save()?;
-    Ok(())
+    current_settings.refresh_token = None;
+    current_settings.save()
 }
 
-pub async fn start_client(
-    handler: Handle<()>,
-    socket: Arc<Mutex<Client>>,
-    profile: String,
-) -> Result<()> {
-    let mut client = socket.lock().await;
+pub async fn logout(client: Arc<Client>) -> Result<()> {
+    if let Some(auth_info) = client.set_auth_info(None) {
+        if let Err(e) = client.logout(&auth_info.access_token).await {
+            error!("Can't revoke session: {}", e);
+        }
+    }
+    clear_saved_data().await
+}
+
+pub async fn start_client(handler: Handle<()>, client: Arc<Client>, profile: String) -> Result<()> {
     let resources = client.get_resources(&profile).await?;
     let remote_directory = validation::new_remote_directory(resources);
     let settings = SETTINGS.get().expect("Can't get settings").lock().await;
     let game_dir = settings.game_dir.clone();
     let ram = settings.ram;
     let profile = client.get_profile(&profile).await?.profile;
+    let hash_algorithm = client.hash_algorithm();
     let watcher =
-        validation::validate_profile(&profile, &remote_directory, handler.clone()).await?;
-    let auth_info = client.auth_info.clone();
-    drop(client);
+        validation::validate_profile(&profile, &remote_directory, hash_algorithm, handler.clone())
+            .await?;
+    let auth_info = client.get_auth_info();
     PLAYING.set(()).expect("Can't set playing status");
     let jvm = game::create_jvm(profile.clone(), &game_dir, ram)?;
+    let watch_dir = PathBuf::from(&game_dir);
     let watcher_handle: tokio::task::JoinHandle<Result<()>> =
         tokio::task::spawn_blocking(move || loop {
             let event = watcher.receiver.recv()??;
@@ -170,14 +194,22 @@ pub async fn start_client(
                 for path in event.paths {
                     if path.is_file() {
                         error!("Directory {:?}", remote_directory);
-                        if remote_directory.contains_key(&path) {
-                            let remote_file = &remote_directory[&path];
-                            let hashed_file = &validation::create_hashed_file(&path)?;
-                            if hashed_file != remote_file {
-                                return Err(anyhow::anyhow!("Forbidden modification: {:?}", path));
+                        let remote_file =
+                            RelativePath::from_path(path.strip_prefix(&watch_dir).unwrap_or(&path))
+                                .ok()
+                                .and_then(|relative_path| remote_directory.get(&relative_path));
+                        match remote_file {
+                            Some(remote_file) => {
+                                let hashed_file =
+                                    &validation::create_hashed_file(&path, hash_algorithm)?;
+                                if hashed_file != remote_file {
+                                    return Err(anyhow::anyhow!(
+                                        "Forbidden modification: {:?}",
+                                        path
+                                    ));
+                                }
                             }
-                        } else {
-                            return Err(anyhow::anyhow!("Unknown file: {:?}", path));
+                            None => return Err(anyhow::anyhow!("Unknown file: {:?}", path)),
                         }
                     }
                 }
@@ -198,7 +230,7 @@ pub async fn start_client(
     let join_handle = tokio::spawn(async {
         loop {
             let (token, profile, server) = CHANNEL_GET.1.lock().unwrap().recv().unwrap();
-            let mut client = CLIENT.get().unwrap().lock().await;
+            let client = CLIENT.get().unwrap();
             match client.join(&token, &profile, &server).await {
                 Err(e) => CHANNEL_SEND.0.lock().unwrap().send(format!("{}", e)),
                 _ => CHANNEL_SEND.0.lock().unwrap().send("".to_string()),
@@ -1,5 +1,10 @@
+use anyhow::Result;
 use ecies_ed25519::PublicKey;
+use ed25519_dalek::{Signature, Verifier};
+use launcher_api::message::Signed;
 use rand::rngs::OsRng;
+use serde::de::DeserializeOwned;
+use std::convert::TryFrom;
 
 pub mod validation;
 mod watcher;
@@ -7,11 +12,14 @@ mod watcher;
 pub fn get_manager() -> SecurityManager {
     SecurityManager {
         public_key: PublicKey::from_bytes(include_bytes!("../public_key")).unwrap(),
+        sign_public_key: ed25519_dalek::PublicKey::from_bytes(include_bytes!("../sign_public_key"))
+            .unwrap(),
     }
 }
 
 pub struct SecurityManager {
     public_key: PublicKey,
+    sign_public_key: ed25519_dalek::PublicKey,
 }
 
 impl SecurityManager {
@@ -19,4 +27,14 @@ impl SecurityManager {
         let msg = ecies_ed25519::encrypt(&self.public_key, text.as_bytes(), &mut OsRng);
         base64::encode(msg.unwrap())
     }
+
+    pub fn verify<T: DeserializeOwned>(&self, signed: &Signed<T>) -> Result<T> {
+        signed
+            .verify(|payload, signature| {
+                Signature::try_from(signature)
+                    .map(|signature| self.sign_public_key.verify(payload, &signature).is_ok())
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| anyhow::anyhow!("Invalid server signature!"))
+    }
 }
@@ -3,9 +3,11 @@ use crate::security::watcher::WatcherService;
 use anyhow::Result;
 use launcher_api::message::ProfileResourcesResponse;
 use launcher_api::profile::Profile;
-use launcher_api::validation::{HashedFile, OsType, RemoteDirectory, RemoteFile};
+use launcher_api::validation::{
+    Digest, HashAlgorithm, HashedFile, OsType, RelativePath, RemoteDirectory, RemoteFile,
+};
 use log::debug;
-use path_slash::PathExt;
+use sha2::{Digest as _, Sha256};
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
@@ -13,7 +15,7 @@ use web_view::Handle;
 
 pub enum ValidationStatus {
     Success,
-    NeedUpdate(Vec<(String, RemoteFile)>, Vec<PathBuf>),
+    NeedUpdate(Vec<(RelativePath, RemoteFile)>, Vec<PathBuf>),
 }
 
 macro_rules! extend {
@@ -39,18 +41,28 @@ pub fn new_remote_directory(resources: ProfileResourcesResponse) -> RemoteDirect
     files
 }
 
-pub fn create_hashed_file<P: AsRef<Path>>(path: P) -> Result<HashedFile> {
+pub fn create_hashed_file<P: AsRef<Path>>(
+    path: P,
+    algorithm: Option<HashAlgorithm>,
+) -> Result<HashedFile> {
     let mut buffer = Vec::new();
     File::open(path)?.read_to_end(&mut buffer)?;
     Ok(HashedFile {
         size: buffer.len() as u64,
         checksum: t1ha::t1ha2_atonce128(buffer.as_slice(), 1),
+        digest: algorithm.map(|algorithm| Digest {
+            algorithm,
+            hash: match algorithm {
+                HashAlgorithm::Sha256 => format!("{:x}", Sha256::digest(&buffer)),
+            },
+        }),
     })
 }
 
 pub async fn validate_profile(
     profile: &Profile,
     files: &RemoteDirectory,
+    algorithm: Option<HashAlgorithm>,
     handler: Handle<()>,
 ) -> Result<WatcherService> {
     let verify = &profile.update_verify;
@@ -61,7 +73,7 @@ pub async fn validate_profile(
         Ok(())
     })?;
     if let ValidationStatus::NeedUpdate(files_to_update, file_to_remove) =
-        validate(&files, verify, exclude)?
+        validate(&files, verify, exclude, algorithm)?
     {
         debug!("Files to download: {:?}", files_to_update);
         debug!("Files to remove: {:?}", file_to_remove);
@@ -71,13 +83,13 @@ pub async fn validate_profile(
         }
     }
     let watcher = WatcherService::new(profile).expect("Failed to create WatcherService");
-    match validate(&files, verify, exclude)? {
+    match validate(&files, verify, exclude, algorithm)? {
         ValidationStatus::Success => Ok(watcher),
         ValidationStatus::NeedUpdate(files, file_to_remove) => Err(anyhow::anyhow!(
             "Sync error: {:?}",
             files
                 .into_iter()
-                .map(|file| file.0)
+                .map(|file| file.0.to_string())
                 .chain(
                     file_to_remove
                         .into_iter()
@@ -93,16 +105,22 @@ fn validate(
     profile: &RemoteDirectory,
     verify: &[String],
     exclude: &[String],
+    algorithm: Option<HashAlgorithm>,
 ) -> Result<ValidationStatus> {
     let mut remove_files = Vec::new();
-    for dir in verify.iter().map(Path::new).filter(|path| path.is_dir()) {
+    for dir in verify {
+        let dir = RelativePath::new(dir.trim_end_matches('/'))?.to_path();
+        if !dir.is_dir() {
+            continue;
+        }
         for file in walkdir::WalkDir::new(dir)
             .into_iter()
             .filter_map(|e| e.ok())
             .filter(|e| e.path().is_file())
         {
-            let file_path = file.path();
-            if !profile.contains_key(file_path) {
+            let known = RelativePath::from_path(file.path())
+                .map_or(false, |file_path| profile.contains_key(&file_path));
+            if !known {
                 remove_files.push(file.into_path());
             }
         }
@@ -112,11 +130,12 @@ fn validate(
         .iter()
         .filter(|&file| exclude.iter().all(|p| !file.0.starts_with(p)))
         .filter(|&file| {
-            create_hashed_file(file.0).map_or(true, |ref hashed_file| hashed_file != file.1)
+            create_hashed_file(file.0.to_path(), algorithm)
+                .map_or(true, |ref hashed_file| hashed_file != file.1)
         });
     let profile = profile
-        .map(|file| (file.0.to_slash_lossy(), file.1.clone()))
-        .collect::<Vec<(String, RemoteFile)>>();
+        .map(|file| (file.0.clone(), file.1.clone()))
+        .collect::<Vec<(RelativePath, RemoteFile)>>();
     if profile.is_empty() && remove_files.is_empty() {
         Ok(ValidationStatus::Success)
     } else {
@@ -124,36 +143,38 @@ fn validate(
     }
 }
 
-pub fn get_os_type() -> OsType {
+// Native libraries and JRE are loaded in-process, so launcher build arch is used, not OS arch
+// (e.g. x64 launcher emulated on Windows ARM64 needs x64 JRE)
+pub fn get_os_type() -> Result<OsType> {
     #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
     let os_type = OsType::MacOSX64;
+    #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+    let os_type = OsType::MacOSArm64;
     #[cfg(all(target_os = "linux"))]
-    let os_type = {
-        let info = uname::uname().expect("Can't get os info");
-
-        match info.machine.as_ref() {
-            "i686" => OsType::LinuxX32,
-            "x86_64" => OsType::LinuxX64,
-            _ => unreachable!(),
-        }
+    let os_type = if cfg!(target_arch = "x86") {
+        OsType::LinuxX32
+    } else if cfg!(target_arch = "x86_64") {
+        OsType::LinuxX64
+    } else if cfg!(target_arch = "aarch64") {
+        OsType::LinuxArm64
+    } else {
+        return Err(anyhow::anyhow!(
+            "Unsupported architecture: {}",
+            std::env::consts::ARCH
+        ));
     };
     #[cfg(all(target_os = "windows"))]
-    let os_type = {
-        use std::mem;
-        use winapi::um::sysinfoapi::{GetNativeSystemInfo, SYSTEM_INFO_u_s, SYSTEM_INFO};
-        use winapi::um::winnt::{PROCESSOR_ARCHITECTURE_AMD64, PROCESSOR_ARCHITECTURE_INTEL};
-
-        let mut system_info: SYSTEM_INFO = unsafe { mem::zeroed() };
-
-        unsafe { GetNativeSystemInfo(&mut system_info) };
-
-        let s: &SYSTEM_INFO_u_s = unsafe { system_info.u.s() };
-
-        match s.wProcessorArchitecture {
-            PROCESSOR_ARCHITECTURE_INTEL => OsType::WindowsX32,
-            PROCESSOR_ARCHITECTURE_AMD64 => OsType::WindowsX64,
-            _ => unreachable!(),
-        }
+    let os_type = if cfg!(target_arch = "x86") {
+        OsType::WindowsX32
+    } else if cfg!(target_arch = "x86_64") {
+        OsType::WindowsX64
+    } else if cfg!(target_arch = "aarch64") {
+        OsType::WindowsArm64
+    } else {
+        return Err(anyhow::anyhow!(
+            "Unsupported architecture: {}",
+            std::env::consts::ARCH
+        ));
     };
-    os_type
+    Ok(os_type)
 }
@@ -1,32 +1,114 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
 use uuid::Uuid;
 
 use crate::profile::{Profile, ProfileInfo};
-use crate::validation::{OsType, RemoteDirectory};
+use crate::validation::{HashAlgorithm, OsType, RemoteDirectory};
+
+/// Version of the websocket protocol, checked during handshake.
+/// Must be bumped on every wire format change of messages below.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Deserialize, Serialize)]
+pub struct ClientRequest {
+    pub id: u64,
+    pub message: ClientMessage,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ServerResponse {
+    pub id: u64,
+    pub message: ServerMessage,
+}
 
 #[derive(Deserialize, Serialize)]
 pub enum ClientMessage {
+    Handshake(HandshakeMessage),
     Auth(AuthMessage),
+    Refresh(RefreshMessage),
+    Logout(LogoutMessage),
     JoinServer(JoinServerMessage),
     ProfileResources(ProfileResourcesMessage),
     Profile(ProfileMessage),
     ProfilesInfo(ProfilesInfoMessage),
+    UploadTexture(UploadTextureMessage),
 }
 
 #[derive(Deserialize, Serialize)]
 pub enum ServerMessage {
+    Handshake(HandshakeResponse),
     Auth(AuthResponse),
-    ProfileResources(ProfileResourcesResponse),
-    Profile(ProfileResponse),
+    ProfileResources(Signed<ProfileResourcesResponse>),
+    Profile(Signed<ProfileResponse>),
     ProfilesInfo(ProfilesInfoResponse),
     Empty,
     Error(Error),
 }
 
+#[derive(Deserialize, Serialize)]
+#[serde(bound = "")]
+pub struct Signed<T> {
+    pub payload: String,
+    pub signature: Vec<u8>,
+    #[serde(skip)]
+    marker: PhantomData<T>,
+}
+
+impl<T: Serialize> Signed<T> {
+    pub fn sign<F>(value: &T, signer: F) -> serde_json::Result<Self>
+    where
+        F: FnOnce(&[u8]) -> Vec<u8>,
+    {
+        let payload = serde_json::to_string(value)?;
+        let signature = signer(payload.as_bytes());
+        Ok(Signed {
+            payload,
+            signature,
+            marker: PhantomData,
+        })
+    }
+}
+
+impl<T: DeserializeOwned> Signed<T> {
+    pub fn verify<F>(&self, verifier: F) -> Option<T>
+    where
+        F: FnOnce(&[u8], &[u8]) -> bool,
+    {
+        if verifier(self.payload.as_bytes(), &self.signature) {
+            serde_json::from_str(&self.payload).ok()
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct HandshakeMessage {
+    pub protocol_version: u32,
+    pub launcher_version: String,
+    pub os_type: OsType,
+    #[serde(default)]
+    pub hash_algorithms: Vec<HashAlgorithm>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct AuthMessage {
     pub login: String,
     pub password: String,
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RefreshMessage {
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct LogoutMessage {
+    pub access_token: String,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -36,6 +118,22 @@ pub struct JoinServerMessage {
     pub server_id: String,
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureKind {
+    Skin,
+    Cape,
+}
+
+// PNG file content, empty data removes current texture
+#[derive(Deserialize, Serialize)]
+pub struct UploadTextureMessage {
+    pub access_token: String,
+    pub kind: TextureKind,
+    pub data: Vec<u8>,
+    #[serde(default)]
+    pub slim: bool,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct ProfileResourcesMessage {
     pub profile: String,
@@ -62,6 +160,9 @@ pub struct ProfilesInfoResponse {
 
 #[derive(Deserialize, Serialize)]
 pub struct ProfileResourcesResponse {
+    // Signed together with files, so manifest can't be replayed for another profile or os
+    pub profile_name: String,
+    pub os_type: OsType,
     pub profile: RemoteDirectory,
     pub libraries: RemoteDirectory,
     pub assets: RemoteDirectory,
@@ -69,14 +170,70 @@ pub struct ProfileResourcesResponse {
     pub jre: RemoteDirectory,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct HandshakeResponse {
+    pub protocol_version: u32,
+    pub hash_algorithm: Option<HashAlgorithm>,
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthResponse {
     pub uuid: String,
     pub access_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    // Seconds until access token expires
+    #[serde(default)]
+    pub expires_in: u64,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct Error {
+    pub kind: ErrorKind,
     pub msg: String,
+    // Shown to user, so only data that is safe to expose (e.g. lockout seconds, file path)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
 }
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    AuthFailed,
+    InvalidAccessToken,
+    RateLimited,
+    ProfileNotFound,
+    ResourcesNotSynchronized,
+    OutdatedLauncher,
+    OutdatedServer,
+    ProviderUnavailable,
+    ServerDisconnected,
+    Timeout,
+    BadRequest,
+    Internal,
+    #[serde(other)]
+    Unknown,
+}
+
+impl Error {
+    pub fn new<S: Into<String>>(kind: ErrorKind, msg: S) -> Self {
+        Error {
+            kind,
+            msg: msg.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details<S: Into<String>>(mut self, details: S) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for Error {}
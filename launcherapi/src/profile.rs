@@ -1,26 +1,234 @@
+use crate::validation::{Arch, OsName, OsType};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const PROFILE_SCHEMA_VERSION: u32 = 1;
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Profile {
+    pub schema_version: u32,
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     pub version: String,
-    pub libraries: Vec<String>,
+    pub libraries: Vec<Library>,
     pub class_path: Vec<String>,
     pub main_class: String,
     pub update_verify: Vec<String>,
     pub update_exclusion: Vec<String>,
-    pub jvm_args: Vec<String>,
-    pub client_args: Vec<String>,
+    pub jvm_args: Vec<Argument>,
+    pub client_args: Vec<Argument>,
     pub assets: String,
     pub assets_dir: String,
     pub server_name: String,
     pub server_port: u32,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Library {
+    Path(String),
+    Conditional { path: String, rules: Vec<Rule> },
+}
+
+impl Library {
+    pub fn path(&self) -> &str {
+        match self {
+            Library::Path(path) => path,
+            Library::Conditional { path, .. } => path,
+        }
+    }
+
+    pub fn is_allowed(&self, os_type: &OsType, features: Option<&[String]>) -> bool {
+        match self {
+            Library::Path(_) => true,
+            Library::Conditional { rules, .. } => is_allowed(rules, os_type, features),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Argument {
+    Value(String),
+    Conditional {
+        value: ArgumentValue,
+        rules: Vec<Rule>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ArgumentValue {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+pub fn resolve_arguments(
+    arguments: &[Argument],
+    os_type: &OsType,
+    features: &[String],
+) -> Vec<String> {
+    let mut resolved = Vec::new();
+    for argument in arguments {
+        match argument {
+            Argument::Value(value) => resolved.push(value.clone()),
+            Argument::Conditional { value, rules } => {
+                if is_allowed(rules, os_type, Some(features)) {
+                    match value {
+                        ArgumentValue::Single(value) => resolved.push(value.clone()),
+                        ArgumentValue::Multiple(values) => resolved.extend(values.iter().cloned()),
+                    }
+                }
+            }
+        }
+    }
+    resolved
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Rule {
+    pub action: RuleAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<OsRule>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub features: HashMap<String, bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    Allow,
+    Disallow,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OsRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<OsName>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<Arch>,
+    // Mojang regex for OS version, launchserver rejects profiles using it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl Rule {
+    pub fn has_os_version(&self) -> bool {
+        matches!(&self.os, Some(os) if os.version.is_some())
+    }
+
+    // Unknown features (None) are treated as the widest set, so allow rules match and disallow rules don't
+    fn matches(&self, os_type: &OsType, features: Option<&[String]>) -> bool {
+        if let Some(os) = &self.os {
+            if matches!(os.name, Some(name) if name != os_type.name())
+                || matches!(os.arch, Some(arch) if arch != os_type.arch())
+            {
+                return false;
+            }
+            // Version is unknown here, so rule is evaluated to the narrowest result
+            if os.version.is_some() {
+                return self.action == RuleAction::Disallow;
+            }
+        }
+        match features {
+            Some(features) => self
+                .features
+                .iter()
+                .all(|(feature, &enabled)| features.contains(feature) == enabled),
+            None => self.features.is_empty() || self.action == RuleAction::Allow,
+        }
+    }
+}
+
+pub fn is_allowed(rules: &[Rule], os_type: &OsType, features: Option<&[String]>) -> bool {
+    if rules.is_empty() {
+        return true;
+    }
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(os_type, features))
+        .map(|rule| rule.action)
+        == Some(RuleAction::Allow)
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct ProfileInfo {
     pub name: String,
     pub version: String,
     pub description: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(json: &str) -> Vec<Rule> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn empty_rules_allow() {
+        assert!(is_allowed(&[], &OsType::LinuxX64, None));
+    }
+
+    #[test]
+    fn os_name_and_arch() {
+        let rules = rules(r#"[{"action": "allow", "os": {"name": "osx", "arch": "arm64"}}]"#);
+        assert!(is_allowed(&rules, &OsType::MacOSArm64, None));
+        assert!(!is_allowed(&rules, &OsType::MacOSX64, None));
+        assert!(!is_allowed(&rules, &OsType::LinuxArm64, None));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let rules =
+            rules(r#"[{"action": "allow"}, {"action": "disallow", "os": {"name": "windows"}}]"#);
+        assert!(is_allowed(&rules, &OsType::LinuxX64, None));
+        assert!(!is_allowed(&rules, &OsType::WindowsX64, None));
+    }
+
+    #[test]
+    fn features() {
+        let rules = rules(r#"[{"action": "allow", "features": {"is_demo_user": true}}]"#);
+        let demo = vec!["is_demo_user".to_string()];
+        assert!(is_allowed(&rules, &OsType::LinuxX64, Some(&demo)));
+        assert!(!is_allowed(&rules, &OsType::LinuxX64, Some(&[])));
+        // Server doesn't know features, so allowed library is kept
+        assert!(is_allowed(&rules, &OsType::LinuxX64, None));
+    }
+
+    #[test]
+    fn os_version_is_narrowest() {
+        let allow = rules(r#"[{"action": "allow", "os": {"name": "osx", "version": "^10\\.5"}}]"#);
+        assert!(allow[0].has_os_version());
+        assert!(!is_allowed(&allow, &OsType::MacOSX64, None));
+        let disallow = rules(
+            r#"[{"action": "allow"}, {"action": "disallow", "os": {"name": "osx", "version": "^10\\.5"}}]"#,
+        );
+        assert!(!is_allowed(&disallow, &OsType::MacOSX64, None));
+        assert!(is_allowed(&disallow, &OsType::WindowsX64, None));
+    }
+
+    #[test]
+    fn conditional_arguments() {
+        let arguments: Vec<Argument> = serde_json::from_str(
+            r#"["--username", {"value": ["--width", "800"], "rules": [{"action": "allow", "features": {"has_custom_resolution": true}}]}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            resolve_arguments(&arguments, &OsType::LinuxX64, &[]),
+            vec!["--username"]
+        );
+        assert_eq!(
+            resolve_arguments(
+                &arguments,
+                &OsType::LinuxX64,
+                &["has_custom_resolution".to_string()]
+            ),
+            vec!["--username", "--width", "800"]
+        );
+    }
+}
@@ -1,11 +1,25 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HashAlgorithm {
+    Sha256,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct Digest {
+    pub algorithm: HashAlgorithm,
+    pub hash: String,
+}
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct HashedFile {
     pub size: u64,
     pub checksum: u128,
+    pub digest: Option<Digest>,
 }
 
 #[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
@@ -13,21 +27,251 @@ pub struct RemoteFile {
     pub uri: String,
     pub size: u64,
     pub checksum: u128,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<Digest>,
 }
 
+// Local file is hashed with negotiated algorithm, so missing remote digest is a mismatch
 impl PartialEq<RemoteFile> for HashedFile {
     fn eq(&self, other: &RemoteFile) -> bool {
-        self.size == other.size && self.checksum == other.checksum
+        self.size == other.size && self.checksum == other.checksum && self.digest == other.digest
+    }
+}
+
+pub type RemoteDirectory = HashMap<RelativePath, RemoteFile>;
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct RelativePath(String);
+
+#[derive(Debug)]
+pub struct InvalidPath(String);
+
+impl RelativePath {
+    pub fn new<S: Into<String>>(path: S) -> Result<Self, InvalidPath> {
+        let path = path.into();
+        let valid = !path.is_empty()
+            && !path.contains(&['\\', ':', '\0'][..])
+            && path
+                .split('/')
+                .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+        if valid {
+            Ok(RelativePath(path))
+        } else {
+            Err(InvalidPath(path))
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, InvalidPath> {
+        let path = path.as_ref();
+        let mut segments = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(segment) => match segment.to_str() {
+                    Some(segment) => segments.push(segment),
+                    None => return Err(InvalidPath(path.to_string_lossy().to_string())),
+                },
+                Component::CurDir => {}
+                _ => return Err(InvalidPath(path.to_string_lossy().to_string())),
+            }
+        }
+        RelativePath::new(segments.join("/"))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn to_path(&self) -> PathBuf {
+        self.0.split('/').collect()
+    }
+
+    pub fn starts_with(&self, base: &str) -> bool {
+        let base = base.trim_end_matches('/');
+        self.0 == base || (self.0.starts_with(base) && self.0[base.len()..].starts_with('/'))
+    }
+}
+
+impl TryFrom<String> for RelativePath {
+    type Error = InvalidPath;
+
+    fn try_from(path: String) -> Result<Self, Self::Error> {
+        RelativePath::new(path)
+    }
+}
+
+impl From<RelativePath> for String {
+    fn from(path: RelativePath) -> Self {
+        path.0
+    }
+}
+
+impl fmt::Display for RelativePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for InvalidPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unsafe file path: {:?}", self.0)
     }
 }
 
-pub type RemoteDirectory = HashMap<PathBuf, RemoteFile>;
+impl std::error::Error for InvalidPath {}
 
-#[derive(Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Deserialize, Serialize, PartialEq, Eq, Hash, Clone, Debug)]
 pub enum OsType {
     LinuxX64,
     LinuxX32,
+    LinuxArm64,
     MacOSX64,
+    MacOSArm64,
     WindowsX64,
     WindowsX32,
+    WindowsArm64,
+}
+
+impl OsType {
+    pub const ALL: [OsType; 8] = [
+        OsType::LinuxX64,
+        OsType::LinuxX32,
+        OsType::LinuxArm64,
+        OsType::MacOSX64,
+        OsType::MacOSArm64,
+        OsType::WindowsX64,
+        OsType::WindowsX32,
+        OsType::WindowsArm64,
+    ];
+
+    pub fn name(&self) -> OsName {
+        match self {
+            OsType::LinuxX64 | OsType::LinuxX32 | OsType::LinuxArm64 => OsName::Linux,
+            OsType::MacOSX64 | OsType::MacOSArm64 => OsName::MacOS,
+            OsType::WindowsX64 | OsType::WindowsX32 | OsType::WindowsArm64 => OsName::Windows,
+        }
+    }
+
+    pub fn arch(&self) -> Arch {
+        match self {
+            OsType::LinuxX32 | OsType::WindowsX32 => Arch::X86,
+            OsType::LinuxX64 | OsType::MacOSX64 | OsType::WindowsX64 => Arch::X64,
+            OsType::LinuxArm64 | OsType::MacOSArm64 | OsType::WindowsArm64 => Arch::Arm64,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum OsName {
+    Linux,
+    #[serde(rename = "osx")]
+    MacOS,
+    Windows,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    X86,
+    X64,
+    Arm64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote_file(digest: Option<Digest>) -> RemoteFile {
+        RemoteFile {
+            uri: "http://127.0.0.1/files/a.jar".to_string(),
+            size: 3,
+            checksum: 42,
+            digest,
+        }
+    }
+
+    fn sha256(hash: &str) -> Option<Digest> {
+        Some(Digest {
+            algorithm: HashAlgorithm::Sha256,
+            hash: hash.to_string(),
+        })
+    }
+
+    #[test]
+    fn hashed_file_matches_digest() {
+        let file = HashedFile {
+            size: 3,
+            checksum: 42,
+            digest: sha256("abc"),
+        };
+        assert!(file == remote_file(sha256("abc")));
+        assert!(file != remote_file(sha256("abd")));
+        // Server negotiated digests, but didn't send one
+        assert!(file != remote_file(None));
+    }
+
+    #[test]
+    fn hashed_file_without_digest_requires_it() {
+        let file = HashedFile {
+            size: 3,
+            checksum: 42,
+            digest: None,
+        };
+        assert!(file != remote_file(sha256("abc")));
+        // Old servers don't negotiate digests, only checksum is compared
+        assert!(file == remote_file(None));
+    }
+
+    #[test]
+    fn relative_path_rejects_traversal() {
+        for path in &[
+            "",
+            "..",
+            "../secret",
+            "libraries/../../secret",
+            "./libraries",
+            "libraries//a.jar",
+            "/etc/passwd",
+            "libraries/",
+            "C:/Windows",
+            "libraries\\..\\a.jar",
+            "a\0b",
+        ] {
+            assert!(RelativePath::new(*path).is_err(), "{:?} is accepted", path);
+        }
+        assert_eq!(
+            RelativePath::new("libraries/a.jar").unwrap().as_str(),
+            "libraries/a.jar"
+        );
+    }
+
+    #[test]
+    fn relative_path_from_path() {
+        assert_eq!(
+            RelativePath::from_path("./libraries/a.jar")
+                .unwrap()
+                .as_str(),
+            "libraries/a.jar"
+        );
+        assert!(RelativePath::from_path("libraries/../a.jar").is_err());
+        assert!(RelativePath::from_path("/libraries/a.jar").is_err());
+    }
+
+    #[test]
+    fn relative_path_deserialize_is_validated() {
+        assert!(serde_json::from_str::<RelativePath>(r#""../a.jar""#).is_err());
+        let directory: Result<RemoteDirectory, _> = serde_json::from_str(
+            r#"{"../../.bashrc": {"uri": "http://127.0.0.1/a", "size": 1, "checksum": 1}}"#,
+        );
+        assert!(directory.is_err());
+    }
+
+    #[test]
+    fn relative_path_starts_with() {
+        let path = RelativePath::new("natives/linux/a.so").unwrap();
+        assert!(path.starts_with("natives"));
+        assert!(path.starts_with("natives/"));
+        assert!(!path.starts_with("native"));
+    }
 }
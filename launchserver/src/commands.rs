@@ -1,4 +1,6 @@
-use log::info;
+use anyhow::Result;
+use launcher_api::profile::Profile;
+use log::{error, info, warn};
 use rustyline::completion::{extract_word, Completer};
 use rustyline::error::ReadlineError;
 use rustyline::Config as LineConfig;
@@ -6,10 +8,14 @@ use rustyline::{CompletionType, Context, EditMode, Editor, OutputStreamType};
 use rustyline_derive::{Helper, Highlighter, Hinter, Validator};
 use std::collections::HashMap;
 use std::ops::DerefMut;
+use std::path::Path;
 use std::process::exit;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::config::FileAuthProvider;
+use crate::import;
+use crate::import::ImportReport;
 use crate::server::profile;
 use crate::LaunchServer;
 
@@ -136,6 +142,31 @@ pub async fn start(server: Arc<RwLock<LaunchServer>>) {
 fn register_command(helper: &mut CommandHelper) {
     helper.new_command("rehash", "Update checksum of profile files", rehash);
     helper.new_command("sync", "Sync profile list between server and client", sync);
+    helper.new_command(
+        "import_version",
+        "Create profile from version json: import_version <path> <profile name>",
+        import_version,
+    );
+    helper.new_command(
+        "import_gravit",
+        "Create profile from GravitLauncher: import_gravit <profile> <updates dir> [name]",
+        import_gravit,
+    );
+    helper.new_command(
+        "user_add",
+        "Add user to file auth provider: user_add <username> <password>",
+        user_add,
+    );
+    helper.new_command(
+        "user_remove",
+        "Remove user from file auth provider: user_remove <username>",
+        user_remove,
+    );
+    helper.new_command(
+        "user_reset",
+        "Change user password in file auth provider: user_reset <username> <password>",
+        user_reset,
+    );
 }
 
 pub fn rehash(server: &mut LaunchServer, args: &[&str]) {
@@ -152,3 +183,102 @@ pub fn sync(server: &mut LaunchServer, _args: &[&str]) {
     server.profiles_info = profiles_info;
     info!("Sync was successfully finished!");
 }
+
+pub fn import_version(_server: &mut LaunchServer, args: &[&str]) {
+    if args.len() < 2 {
+        error!("Usage: import_version <path to version.json> <profile name>");
+        return;
+    }
+    save_imported_profile(
+        import::mojang::import_version(Path::new(args[0]), args[1]),
+        "put client jar near it and use 'sync' and 'rehash'",
+    );
+}
+
+pub fn import_gravit(_server: &mut LaunchServer, args: &[&str]) {
+    if args.len() < 2 {
+        error!("Usage: import_gravit <path to profile json> <path to updates dir> [profile name]");
+        return;
+    }
+    save_imported_profile(
+        import::gravit::import_profile(
+            Path::new(args[0]),
+            Path::new(args[1]),
+            args.get(2).copied(),
+        ),
+        "use 'sync' and 'rehash'",
+    );
+}
+
+pub fn user_add(server: &mut LaunchServer, args: &[&str]) {
+    if args.len() < 2 {
+        error!("Usage: user_add <username> <password>");
+        return;
+    }
+    if let Some(provider) = get_file_provider(server) {
+        match provider.add_user(args[0], args[1]) {
+            Ok(uuid) => info!("User '{}' was added with uuid {}", args[0], uuid),
+            Err(e) => error!("Can't add user: {:#}", e),
+        }
+    }
+}
+
+pub fn user_remove(server: &mut LaunchServer, args: &[&str]) {
+    if args.is_empty() {
+        error!("Usage: user_remove <username>");
+        return;
+    }
+    if let Some(provider) = get_file_provider(server) {
+        match provider.remove_user(args[0]) {
+            Ok(uuid) => {
+                server.sessions.revoke_user(&uuid);
+                info!("User '{}' was removed", args[0]);
+            }
+            Err(e) => error!("Can't remove user: {:#}", e),
+        }
+    }
+}
+
+pub fn user_reset(server: &mut LaunchServer, args: &[&str]) {
+    if args.len() < 2 {
+        error!("Usage: user_reset <username> <password>");
+        return;
+    }
+    if let Some(provider) = get_file_provider(server) {
+        match provider.reset_user(args[0], args[1]) {
+            Ok(uuid) => {
+                server.sessions.revoke_user(&uuid);
+                info!("Password of user '{}' was changed", args[0]);
+            }
+            Err(e) => error!("Can't reset user: {:#}", e),
+        }
+    }
+}
+
+fn get_file_provider(server: &LaunchServer) -> Option<&FileAuthProvider> {
+    let provider = server.config.auth.find::<FileAuthProvider>();
+    if provider.is_none() {
+        error!("File auth provider is not used, check your config!");
+    }
+    provider
+}
+
+fn save_imported_profile(result: Result<(Profile, ImportReport)>, hint: &str) {
+    let result = result.and_then(|(profile, mut report)| {
+        let path = import::save_profile(&profile)?;
+        report.missing_libraries = import::find_missing_libraries(&profile);
+        Ok((path, report))
+    });
+    match result {
+        Ok((path, report)) => {
+            for skipped in &report.skipped {
+                warn!("{}", skipped);
+            }
+            for library in &report.missing_libraries {
+                warn!("Library '{}' is missing in static/libraries", library);
+            }
+            info!("Profile was saved to {:?}, {}", path, hint);
+        }
+        Err(e) => error!("Can't import profile: {:#}", e),
+    }
+}
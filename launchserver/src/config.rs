@@ -1,11 +1,19 @@
-use crate::config::auth::{AuthProvide, AuthResult, Entry};
-use crate::config::AuthProvider::{Empty, JSON};
-use anyhow::Result;
+use crate::config::auth::file::FileStore;
+use crate::config::auth::offline::OfflineSession;
+use crate::config::auth::{AsAny, AuthProvide, PROVIDERS};
+use anyhow::{Context, Result};
+use ipnet::IpNet;
 use launcher_api::config::Configurable;
-use log::error;
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use sqlx::any::AnyPool;
+use std::any::Any;
 use std::clone::Clone;
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, RwLock};
 use uuid::Uuid;
 
 pub(crate) mod auth;
@@ -15,26 +23,137 @@ mod texture;
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     pub bind_address: String,
+    // Forwarding headers are honored only from these peers
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
     pub auth: AuthProvider,
+    #[serde(default)]
+    pub auth_limiter: AuthLimiterConfig,
+    #[serde(default)]
+    pub sessions: SessionConfig,
     pub texture: TextureProvider,
     pub file_server: String,
     pub websocket_url: String,
     pub project_name: String,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AuthLimiterConfig {
+    pub enabled: bool,
+    pub ip: LimitRule,
+    pub login: LimitRule,
+}
+
+// Durations are in seconds
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LimitRule {
+    pub max_attempts: u32,
+    pub window: u64,
+    pub lockout: u64,
+    #[serde(default = "default_backoff")]
+    pub backoff: f64,
+    pub max_lockout: u64,
+}
+
+fn default_backoff() -> f64 {
+    2.0
+}
+
+impl Default for AuthLimiterConfig {
+    fn default() -> Self {
+        AuthLimiterConfig {
+            enabled: true,
+            ip: LimitRule {
+                max_attempts: 20,
+                window: 60,
+                lockout: 60,
+                backoff: default_backoff(),
+                max_lockout: 3600,
+            },
+            login: LimitRule {
+                max_attempts: 5,
+                window: 300,
+                lockout: 60,
+                backoff: default_backoff(),
+                max_lockout: 3600,
+            },
+        }
+    }
+}
+
+// Durations are in seconds
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SessionConfig {
+    pub file: String,
+    pub access_token_ttl: u64,
+    pub refresh_token_ttl: u64,
+    pub join_ttl: u64,
+    pub cleanup_interval: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            file: "sessions.json".to_string(),
+            access_token_ttl: 24 * 60 * 60,
+            refresh_token_ttl: 30 * 24 * 60 * 60,
+            join_ttl: 30,
+            cleanup_interval: 10 * 60,
+        }
+    }
+}
+
+// Uploaded textures take precedence, url templates are used for users without them
+// Templates are emitted for every such user, server can't know if the file exists,
+// so cape template is ignored when storage is enabled
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct TextureProvider {
-    skin_url: String,
-    cape_url: String,
+    #[serde(default)]
+    skin_url: Option<String>,
+    #[serde(default)]
+    cape_url: Option<String>,
+    #[serde(default)]
+    pub storage: TextureStorageConfig,
 }
 
+// Max size is in bytes, max scale allows HD textures (e.g. 2 for 128x128 skins)
+// Disabled by default, url must point to public address of /textures route
 #[derive(Serialize, Deserialize, Clone)]
-pub enum AuthProvider {
-    Empty,
-    JSON(JsonAuthProvider),
+#[serde(rename_all = "camelCase", default)]
+pub struct TextureStorageConfig {
+    pub enabled: bool,
+    pub directory: String,
+    pub url: String,
+    pub max_size: usize,
+    pub max_scale: u32,
 }
 
+impl Default for TextureStorageConfig {
+    fn default() -> Self {
+        TextureStorageConfig {
+            enabled: false,
+            directory: "textures".to_string(),
+            url: "http://127.0.0.1:8080/textures".to_string(),
+            max_size: 256 * 1024,
+            max_scale: 1,
+        }
+    }
+}
+
+// Provider selected by "type" field, the rest of the object is provider config
+#[derive(Clone)]
+pub struct AuthProvider {
+    config: Value,
+    provider: Arc<dyn AuthProvide>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct EmptyAuthProvider {}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct JsonAuthProvider {
@@ -47,6 +166,115 @@ pub struct JsonAuthProvider {
     pub client: Option<Client>,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SqlAuthProvider {
+    pub url: String,
+    #[serde(default)]
+    pub table: SqlTable,
+    #[serde(default)]
+    pub queries: SqlQueries,
+    #[serde(skip)]
+    pub pool: Option<AnyPool>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SqlTable {
+    pub name: String,
+    pub uuid_column: String,
+    pub username_column: String,
+    pub password_column: String,
+    pub access_token_column: String,
+    pub server_id_column: String,
+}
+
+impl Default for SqlTable {
+    fn default() -> Self {
+        SqlTable {
+            name: "users".to_string(),
+            uuid_column: "uuid".to_string(),
+            username_column: "username".to_string(),
+            password_column: "password".to_string(),
+            access_token_column: "access_token".to_string(),
+            server_id_column: "server_id".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SqlQueries {
+    pub select_by_username: Option<String>,
+    pub select_by_uuid: Option<String>,
+    pub update_access_token: Option<String>,
+    pub update_server_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileAuthProvider {
+    pub users_file: String,
+    #[serde(default = "default_store_file")]
+    pub store_file: String,
+    #[serde(skip)]
+    pub store: Arc<RwLock<FileStore>>,
+    // Store file is written outside of store lock, older snapshots are skipped by version
+    #[serde(skip)]
+    pub saved_version: Arc<Mutex<u64>>,
+}
+
+fn default_store_file() -> String {
+    "users_store.json".to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct OfflineAuthProvider {
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(skip)]
+    pub sessions: Arc<RwLock<HashMap<Uuid, OfflineSession>>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainAuthProvider {
+    pub providers: Vec<ChainLink>,
+    // Which link authorized the account, so entry updates go to the same provider
+    #[serde(skip)]
+    pub owners: Arc<RwLock<HashMap<Uuid, usize>>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainLink {
+    pub provider: AuthProvider,
+    #[serde(default = "ChainAction::next")]
+    pub on_not_found: ChainAction,
+    #[serde(default = "ChainAction::stop")]
+    pub on_wrong_password: ChainAction,
+    #[serde(default = "ChainAction::stop")]
+    pub on_unavailable: ChainAction,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ChainAction {
+    Next,
+    Stop,
+}
+
+impl ChainAction {
+    fn next() -> Self {
+        ChainAction::Next
+    }
+
+    fn stop() -> Self {
+        ChainAction::Stop
+    }
+}
+
 impl Configurable for Config {}
 
 impl Default for Config {
@@ -54,10 +282,14 @@ impl Default for Config {
         Config {
             file_server: "http://127.0.0.1:8080/files".to_string(),
             bind_address: "127.0.0.1:8080".to_string(),
-            auth: Empty,
+            trusted_proxies: Vec::new(),
+            auth: AuthProvider::default(),
+            auth_limiter: AuthLimiterConfig::default(),
+            sessions: SessionConfig::default(),
             texture: TextureProvider {
-                skin_url: "http://example.com/skin/{username}.png".to_string(),
-                cape_url: "http://example.com/cape/{username}.png".to_string(),
+                skin_url: None,
+                cape_url: None,
+                storage: TextureStorageConfig::default(),
             },
             websocket_url: "ws://127.0.0.1:8080".to_string(),
             project_name: "NSL".to_string(),
@@ -73,72 +305,105 @@ impl Config {
 }
 
 impl AuthProvider {
+    // Provider is built again from config, clones made before init keep the old one
     pub fn init(&mut self) -> Result<()> {
-        match self {
-            Empty => {
-                error!("Auth provider not found, check your config!");
-                Err(anyhow::anyhow!(
-                    "Can't authorize account. Please contact to administration!".to_string()
-                ))
-            }
-            JSON(json) => json.init(),
-        }
+        let (name, config) = AuthProvider::normalize(self.config.clone())
+            .ok_or_else(|| anyhow::anyhow!("Auth provider must contain \"type\" field"))?;
+        let mut provider = AuthProvider::create(&name, config)?;
+        provider.init()?;
+        self.provider = Arc::from(provider);
+        Ok(())
     }
 
-    pub async fn auth(&self, login: &str, password: &str, ip: &str) -> Result<AuthResult> {
-        match self {
-            Empty => {
-                error!("Auth provider not found, check your config!");
-                Err(anyhow::anyhow!(
-                    "Can't authorize account. Please contact to administration!".to_string()
-                ))
-            }
-            JSON(json) => json.auth(login, password, ip).await,
-        }
+    fn create(name: &str, config: Map<String, Value>) -> Result<Box<dyn AuthProvide>> {
+        let factory = PROVIDERS
+            .read()
+            .expect("Auth providers registry is poisoned")
+            .get_factory(name)?;
+        factory(Value::Object(config))
+            .with_context(|| format!("Invalid config of '{}' auth provider", name))
     }
 
-    pub async fn get_entry(&self, uuid: &Uuid) -> Result<Entry> {
-        match self {
-            Empty => {
-                error!("Auth provider not found, check your config!");
-                Err(anyhow::anyhow!(
-                    "Can't get account entry. Please contact to administration!".to_string()
-                ))
-            }
-            JSON(json) => json.get_entry(uuid).await,
-        }
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        AsAny::as_any(self.provider.as_ref()).downcast_ref()
     }
-    pub async fn get_entry_from_name(&self, username: &str) -> Result<Entry> {
-        match self {
-            Empty => {
-                error!("Auth provider not found, check your config!");
-                Err(anyhow::anyhow!(
-                    "Can't get account entry. Please contact to administration!".to_string()
-                ))
-            }
-            JSON(json) => json.get_entry_from_name(username).await,
-        }
+
+    // Provider itself or its chain member
+    pub fn find<T: Any>(&self) -> Option<&T> {
+        self.downcast_ref().or_else(|| {
+            self.downcast_ref::<ChainAuthProvider>()
+                .and_then(|chain| chain.find())
+        })
     }
-    pub async fn update_access_token(&self, uuid: &Uuid, token: &str) -> Result<()> {
-        match self {
-            Empty => {
-                error!("Auth provider not found, check your config!");
-                Err(anyhow::anyhow!(
-                    "Can't authorize account. Please contact to administration!".to_string()
-                ))
+
+    // Old configs used externally tagged enum: "Empty" or {"JSON": {...}}
+    // A list of providers is a shorthand for chain with default policies
+    fn normalize(config: Value) -> Option<(String, Map<String, Value>)> {
+        match config {
+            Value::String(name) => Some((name.to_lowercase(), Map::new())),
+            Value::Array(providers) => {
+                let links = providers
+                    .into_iter()
+                    .map(|provider| match provider {
+                        Value::Object(link) if link.contains_key("provider") => Value::Object(link),
+                        provider => serde_json::json!({ "provider": provider }),
+                    })
+                    .collect();
+                let mut config = Map::new();
+                config.insert("providers".to_string(), Value::Array(links));
+                Some(("chain".to_string(), config))
             }
-            JSON(json) => json.update_access_token(uuid, token).await,
+            Value::Object(mut map) => match map.remove("type") {
+                Some(Value::String(name)) => Some((name, map)),
+                Some(_) => None,
+                None if map.len() == 1 => {
+                    let (name, config) = map.into_iter().next()?;
+                    match config {
+                        Value::Object(config) => Some((name.to_lowercase(), config)),
+                        _ => None,
+                    }
+                }
+                None => None,
+            },
+            _ => None,
         }
     }
-    pub async fn update_server_id(&self, uuid: &Uuid, server_id: &str) -> Result<()> {
-        match self {
-            Empty => {
-                error!("Auth provider not found, check your config!");
-                Err(anyhow::anyhow!(
-                    "Can't authorize account. Please contact to administration!".to_string()
-                ))
-            }
-            JSON(json) => json.update_server_id(uuid, server_id).await,
+}
+
+impl Default for AuthProvider {
+    fn default() -> Self {
+        AuthProvider {
+            config: serde_json::json!({ "type": "empty" }),
+            provider: Arc::new(EmptyAuthProvider::default()),
         }
     }
 }
+
+impl Deref for AuthProvider {
+    type Target = dyn AuthProvide;
+
+    fn deref(&self) -> &Self::Target {
+        self.provider.as_ref()
+    }
+}
+
+impl Serialize for AuthProvider {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.config.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AuthProvider {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (name, config) = AuthProvider::normalize(Value::deserialize(deserializer)?)
+            .ok_or_else(|| D::Error::custom("Auth provider must contain \"type\" field"))?;
+        let provider = AuthProvider::create(&name, config.clone())
+            .map_err(|e| D::Error::custom(format!("{:#}", e)))?;
+        let mut config = config;
+        config.insert("type".to_string(), Value::String(name));
+        Ok(AuthProvider {
+            config: Value::Object(config),
+            provider: Arc::from(provider),
+        })
+    }
+}
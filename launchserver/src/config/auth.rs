@@ -1,14 +1,31 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
-use reqwest::Client;
+use once_cell::sync::Lazy;
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::RwLock;
 use uuid::Uuid;
 
-use crate::config::JsonAuthProvider;
+use crate::config::{
+    ChainAuthProvider, EmptyAuthProvider, FileAuthProvider, JsonAuthProvider, OfflineAuthProvider,
+    SqlAuthProvider,
+};
 use reqwest::header::HeaderName;
 use std::str::FromStr;
 use warp::http::HeaderMap;
 
+mod chain;
+mod empty;
+pub(crate) mod file;
+pub(crate) mod offline;
+mod password;
+mod sql;
+
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Entry {
@@ -22,10 +39,63 @@ pub struct Entry {
 pub struct AuthResult {
     pub uuid: Option<Uuid>,
     pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<AuthFailure>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum AuthFailure {
+    NotFound,
+    WrongPassword,
+}
+
+impl AuthResult {
+    pub fn success(uuid: Uuid) -> Self {
+        AuthResult {
+            uuid: Some(uuid),
+            message: None,
+            failure: None,
+        }
+    }
+
+    pub fn failure(failure: AuthFailure, message: &str) -> Self {
+        AuthResult {
+            uuid: None,
+            message: Some(message.to_string()),
+            failure: Some(failure),
+        }
+    }
+}
+
+// Returned by providers for unknown accounts, other errors mean provider failure
+#[derive(Debug)]
+pub struct EntryNotFound;
+
+impl fmt::Display for EntryNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Entry not found")
+    }
+}
+
+impl std::error::Error for EntryNotFound {}
+
+pub fn is_not_found(error: &anyhow::Error) -> bool {
+    error.is::<EntryNotFound>()
+}
+
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[async_trait]
-pub trait AuthProvide {
+pub trait AuthProvide: AsAny + Send + Sync {
     fn init(&mut self) -> Result<()>;
     async fn auth(&self, login: &str, password: &str, ip: &str) -> Result<AuthResult>;
     async fn get_entry(&self, uuid: &Uuid) -> Result<Entry>;
@@ -34,6 +104,64 @@ pub trait AuthProvide {
     async fn update_server_id(&self, uuid: &Uuid, server_id: &str) -> Result<()>;
 }
 
+pub type ProviderFactory = fn(Value) -> Result<Box<dyn AuthProvide>>;
+
+// Other providers can be added with PROVIDERS.write().unwrap().register::<T>("name")
+pub static PROVIDERS: Lazy<RwLock<ProviderRegistry>> =
+    Lazy::new(|| RwLock::new(ProviderRegistry::default()));
+
+pub struct ProviderRegistry {
+    factories: HashMap<String, ProviderFactory>,
+}
+
+impl ProviderRegistry {
+    pub fn register<T: AuthProvide + DeserializeOwned + 'static>(&mut self, name: &str) {
+        self.factories
+            .insert(name.to_string(), ProviderRegistry::create_provider::<T>);
+    }
+
+    // Factory is copied out, so the registry isn't locked while nested providers are created
+    pub fn get_factory(&self, name: &str) -> Result<ProviderFactory> {
+        self.factories
+            .get(name)
+            .copied()
+            .with_context(|| format!("Unknown auth provider type '{}'", name))
+    }
+
+    fn create_provider<T: AuthProvide + DeserializeOwned + 'static>(
+        config: Value,
+    ) -> Result<Box<dyn AuthProvide>> {
+        Ok(Box::new(serde_json::from_value::<T>(config)?))
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        let mut registry = ProviderRegistry {
+            factories: HashMap::new(),
+        };
+        registry.register::<EmptyAuthProvider>("empty");
+        registry.register::<JsonAuthProvider>("json");
+        registry.register::<SqlAuthProvider>("sql");
+        registry.register::<FileAuthProvider>("file");
+        registry.register::<OfflineAuthProvider>("offline");
+        registry.register::<ChainAuthProvider>("chain");
+        registry
+    }
+}
+
+impl JsonAuthProvider {
+    async fn fetch_entry(&self, request: Value) -> Result<Entry> {
+        let client = self.client.as_ref().unwrap();
+
+        let response = client.post(&self.entry_url).json(&request).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(EntryNotFound.into());
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+}
+
 #[async_trait]
 impl AuthProvide for JsonAuthProvider {
     fn init(&mut self) -> Result<()> {
@@ -68,27 +196,12 @@ impl AuthProvide for JsonAuthProvider {
     }
 
     async fn get_entry(&self, uuid: &Uuid) -> Result<Entry> {
-        let client = self.client.as_ref().unwrap();
-
-        Ok(client
-            .post(&self.entry_url)
-            .json(&serde_json::json!({ "uuid": uuid }))
-            .send()
-            .await?
-            .json()
-            .await?)
+        self.fetch_entry(serde_json::json!({ "uuid": uuid })).await
     }
 
     async fn get_entry_from_name(&self, username: &str) -> Result<Entry> {
-        let client = self.client.as_ref().unwrap();
-
-        Ok(client
-            .post(&self.entry_url)
-            .json(&serde_json::json!({ "username": username }))
-            .send()
-            .await?
-            .json()
-            .await?)
+        self.fetch_entry(serde_json::json!({ "username": username }))
+            .await
     }
 
     async fn update_access_token(&self, uuid: &Uuid, token: &str) -> Result<()> {
@@ -0,0 +1,146 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::warn;
+use std::any::Any;
+use uuid::Uuid;
+
+use crate::config::auth::{
+    is_not_found, AuthFailure, AuthProvide, AuthResult, Entry, EntryNotFound,
+};
+use crate::config::{ChainAction, ChainAuthProvider, ChainLink};
+
+impl ChainAuthProvider {
+    // First provider of given type among links, nested chains are searched too
+    pub fn find<T: Any>(&self) -> Option<&T> {
+        self.providers.iter().find_map(|link| link.provider.find())
+    }
+
+    fn get_owner(&self, uuid: &Uuid) -> Option<usize> {
+        self.owners
+            .read()
+            .expect("Auth chain owners are poisoned")
+            .get(uuid)
+            .copied()
+    }
+
+    fn set_owner(&self, uuid: Uuid, index: usize) {
+        self.owners
+            .write()
+            .expect("Auth chain owners are poisoned")
+            .insert(uuid, index);
+    }
+
+    fn get_action(link: &ChainLink, result: &AuthResult) -> ChainAction {
+        // Providers that don't report the reason are treated as wrong password, so accounts can't be shadowed
+        match result.failure.unwrap_or(AuthFailure::WrongPassword) {
+            AuthFailure::NotFound => link.on_not_found,
+            AuthFailure::WrongPassword => link.on_wrong_password,
+        }
+    }
+
+    async fn find_entry(&self, uuid: &Uuid) -> Result<(usize, Entry)> {
+        if let Some(index) = self.get_owner(uuid) {
+            return Ok((index, self.providers[index].provider.get_entry(uuid).await?));
+        }
+        let mut failure = None;
+        for (index, link) in self.providers.iter().enumerate() {
+            match link.provider.get_entry(uuid).await {
+                Ok(entry) => {
+                    self.set_owner(*uuid, index);
+                    return Ok((index, entry));
+                }
+                Err(e) if is_not_found(&e) => {}
+                Err(e) => {
+                    warn!("Auth provider #{} is unavailable: {}", index, e);
+                    failure = Some(e);
+                }
+            }
+        }
+        // Entry may exist in unavailable provider
+        Err(failure.unwrap_or_else(|| EntryNotFound.into()))
+    }
+
+    async fn find_owner(&self, uuid: &Uuid) -> Result<usize> {
+        match self.get_owner(uuid) {
+            Some(index) => Ok(index),
+            None => Ok(self.find_entry(uuid).await?.0),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvide for ChainAuthProvider {
+    fn init(&mut self) -> Result<()> {
+        if self.providers.is_empty() {
+            return Err(anyhow::anyhow!("Auth chain doesn't contain any provider"));
+        }
+        for link in &mut self.providers {
+            link.provider.init()?;
+        }
+        Ok(())
+    }
+
+    async fn auth(&self, login: &str, password: &str, ip: &str) -> Result<AuthResult> {
+        let mut last = None;
+        for (index, link) in self.providers.iter().enumerate() {
+            let action = match link.provider.auth(login, password, ip).await {
+                Ok(result) => {
+                    if let Some(uuid) = result.uuid {
+                        self.set_owner(uuid, index);
+                        return Ok(result);
+                    }
+                    let action = ChainAuthProvider::get_action(link, &result);
+                    last = Some(Ok(result));
+                    action
+                }
+                Err(e) => {
+                    warn!("Auth provider #{} is unavailable: {}", index, e);
+                    last = Some(Err(e));
+                    link.on_unavailable
+                }
+            };
+            if action == ChainAction::Stop {
+                break;
+            }
+        }
+        last.unwrap_or_else(|| Err(anyhow::anyhow!("Auth chain doesn't contain any provider")))
+    }
+
+    async fn get_entry(&self, uuid: &Uuid) -> Result<Entry> {
+        Ok(self.find_entry(uuid).await?.1)
+    }
+
+    async fn get_entry_from_name(&self, username: &str) -> Result<Entry> {
+        let mut failure = None;
+        for (index, link) in self.providers.iter().enumerate() {
+            match link.provider.get_entry_from_name(username).await {
+                Ok(entry) => {
+                    self.set_owner(entry.uuid, index);
+                    return Ok(entry);
+                }
+                Err(e) if is_not_found(&e) => {}
+                Err(e) => {
+                    warn!("Auth provider #{} is unavailable: {}", index, e);
+                    failure = Some(e);
+                }
+            }
+        }
+        Err(failure.unwrap_or_else(|| EntryNotFound.into()))
+    }
+
+    async fn update_access_token(&self, uuid: &Uuid, token: &str) -> Result<()> {
+        let index = self.find_owner(uuid).await?;
+        self.providers[index]
+            .provider
+            .update_access_token(uuid, token)
+            .await
+    }
+
+    async fn update_server_id(&self, uuid: &Uuid, server_id: &str) -> Result<()> {
+        let index = self.find_owner(uuid).await?;
+        self.providers[index]
+            .provider
+            .update_server_id(uuid, server_id)
+            .await
+    }
+}
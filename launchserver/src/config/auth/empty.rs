@@ -0,0 +1,47 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::error;
+use uuid::Uuid;
+
+use crate::config::auth::{AuthProvide, AuthResult, Entry};
+use crate::config::EmptyAuthProvider;
+
+#[async_trait]
+impl AuthProvide for EmptyAuthProvider {
+    fn init(&mut self) -> Result<()> {
+        error!("Auth provider not found, check your config!");
+        Err(anyhow::anyhow!(
+            "Can't authorize account. Please contact to administration!".to_string()
+        ))
+    }
+
+    async fn auth(&self, _login: &str, _password: &str, _ip: &str) -> Result<AuthResult> {
+        Err(anyhow::anyhow!(
+            "Can't authorize account. Please contact to administration!".to_string()
+        ))
+    }
+
+    async fn get_entry(&self, _uuid: &Uuid) -> Result<Entry> {
+        Err(anyhow::anyhow!(
+            "Can't get account entry. Please contact to administration!".to_string()
+        ))
+    }
+
+    async fn get_entry_from_name(&self, _username: &str) -> Result<Entry> {
+        Err(anyhow::anyhow!(
+            "Can't get account entry. Please contact to administration!".to_string()
+        ))
+    }
+
+    async fn update_access_token(&self, _uuid: &Uuid, _token: &str) -> Result<()> {
+        Err(anyhow::anyhow!(
+            "Can't authorize account. Please contact to administration!".to_string()
+        ))
+    }
+
+    async fn update_server_id(&self, _uuid: &Uuid, _server_id: &str) -> Result<()> {
+        Err(anyhow::anyhow!(
+            "Can't authorize account. Please contact to administration!".to_string()
+        ))
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, RwLockReadGuard, RwLockWriteGuard};
+use uuid::Uuid;
+
+use crate::config::auth::{password, AuthFailure, AuthProvide, AuthResult, Entry, EntryNotFound};
+use crate::config::FileAuthProvider;
+
+#[derive(Default)]
+pub struct FileStore {
+    users: Vec<User>,
+    sessions: HashMap<Uuid, Session>,
+    // Incremented on every sessions change
+    version: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct UsersFile {
+    #[serde(default)]
+    users: Vec<User>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct User {
+    username: String,
+    uuid: Uuid,
+    password: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    access_token: Option<String>,
+    server_id: Option<String>,
+}
+
+impl FileAuthProvider {
+    fn read(&self) -> RwLockReadGuard<'_, FileStore> {
+        self.store.read().expect("Users store is poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, FileStore> {
+        self.store.write().expect("Users store is poisoned")
+    }
+
+    fn is_toml(&self) -> bool {
+        self.users_file.ends_with(".toml")
+    }
+
+    fn load_users(&self) -> Result<Vec<User>> {
+        let path = Path::new(&self.users_file);
+        if !path.exists() {
+            warn!(
+                "Users file {:?} doesn't exist, use user_add command to create users",
+                path
+            );
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(path)?;
+        let users: UsersFile = if self.is_toml() {
+            toml::from_str(&content)?
+        } else {
+            serde_json::from_str(&content)?
+        };
+        Ok(users.users)
+    }
+
+    fn save_users(&self, users: &[User]) -> Result<()> {
+        let users = UsersFile {
+            users: users.to_vec(),
+        };
+        let content = if self.is_toml() {
+            toml::to_string_pretty(&users)?
+        } else {
+            serde_json::to_string_pretty(&users)?
+        };
+        fs::write(&self.users_file, content)
+            .with_context(|| format!("Can't save users file {}", self.users_file))
+    }
+
+    fn load_sessions(&self) -> Result<HashMap<Uuid, Session>> {
+        let path = Path::new(&self.store_file);
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn snapshot_sessions(store: &mut FileStore) -> Result<(u64, String)> {
+        store.version += 1;
+        Ok((store.version, serde_json::to_string(&store.sessions)?))
+    }
+
+    fn save_sessions(
+        saved_version: &Mutex<u64>,
+        path: &str,
+        (version, content): (u64, String),
+    ) -> Result<()> {
+        let mut saved_version = saved_version.lock().expect("Users store is poisoned");
+        if *saved_version < version {
+            fs::write(path, content).with_context(|| format!("Can't save users store {}", path))?;
+            *saved_version = version;
+        }
+        Ok(())
+    }
+
+    async fn update_session<F>(&self, uuid: &Uuid, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut Session) + Send,
+    {
+        let snapshot = {
+            let mut store = self.write();
+            if store.users.iter().all(|user| &user.uuid != uuid) {
+                return Err(EntryNotFound.into());
+            }
+            update(store.sessions.entry(*uuid).or_default());
+            FileAuthProvider::snapshot_sessions(&mut store)?
+        };
+        let saved_version = self.saved_version.clone();
+        let path = self.store_file.clone();
+        tokio::task::spawn_blocking(move || {
+            FileAuthProvider::save_sessions(&saved_version, &path, snapshot)
+        })
+        .await?
+    }
+
+    fn find_user(&self, username: &str) -> Option<User> {
+        self.read()
+            .users
+            .iter()
+            .find(|user| user.username.eq_ignore_ascii_case(username))
+            .cloned()
+    }
+
+    fn create_entry(&self, user: &User) -> Entry {
+        let session = self
+            .read()
+            .sessions
+            .get(&user.uuid)
+            .cloned()
+            .unwrap_or_default();
+        Entry {
+            access_token: session.access_token,
+            server_id: session.server_id,
+            uuid: user.uuid,
+            username: user.username.clone(),
+        }
+    }
+
+    pub fn add_user(&self, username: &str, password: &str) -> Result<Uuid> {
+        let password = password::hash(password)?;
+        let mut store = self.write();
+        if store
+            .users
+            .iter()
+            .any(|user| user.username.eq_ignore_ascii_case(username))
+        {
+            return Err(anyhow::anyhow!("User '{}' already exists", username));
+        }
+        let uuid = Uuid::new_v4();
+        store.users.push(User {
+            username: username.to_string(),
+            uuid,
+            password,
+        });
+        self.save_users(&store.users)?;
+        Ok(uuid)
+    }
+
+    pub fn remove_user(&self, username: &str) -> Result<Uuid> {
+        let mut store = self.write();
+        let index = store
+            .users
+            .iter()
+            .position(|user| user.username.eq_ignore_ascii_case(username))
+            .with_context(|| format!("User '{}' doesn't exist", username))?;
+        let user = store.users.remove(index);
+        store.sessions.remove(&user.uuid);
+        self.save_users(&store.users)?;
+        let snapshot = FileAuthProvider::snapshot_sessions(&mut store)?;
+        FileAuthProvider::save_sessions(&self.saved_version, &self.store_file, snapshot)?;
+        Ok(user.uuid)
+    }
+
+    pub fn reset_user(&self, username: &str, password: &str) -> Result<Uuid> {
+        let password = password::hash(password)?;
+        let mut store = self.write();
+        let user = store
+            .users
+            .iter_mut()
+            .find(|user| user.username.eq_ignore_ascii_case(username))
+            .with_context(|| format!("User '{}' doesn't exist", username))?;
+        user.password = password;
+        let uuid = user.uuid;
+        store.sessions.remove(&uuid);
+        self.save_users(&store.users)?;
+        let snapshot = FileAuthProvider::snapshot_sessions(&mut store)?;
+        FileAuthProvider::save_sessions(&self.saved_version, &self.store_file, snapshot)?;
+        Ok(uuid)
+    }
+}
+
+#[async_trait]
+impl AuthProvide for FileAuthProvider {
+    fn init(&mut self) -> Result<()> {
+        let users = self.load_users()?;
+        let sessions = self.load_sessions()?;
+        let mut store = self.write();
+        store.users = users;
+        store.sessions = sessions;
+        Ok(())
+    }
+
+    async fn auth(&self, login: &str, password: &str, _ip: &str) -> Result<AuthResult> {
+        let user = match self.find_user(login) {
+            Some(user) => user,
+            None => {
+                return Ok(AuthResult::failure(
+                    AuthFailure::NotFound,
+                    "Wrong login or password",
+                ))
+            }
+        };
+        match password::verify(password, &user.password).await {
+            Ok(true) => Ok(AuthResult::success(user.uuid)),
+            Ok(false) => Ok(AuthResult::failure(
+                AuthFailure::WrongPassword,
+                "Wrong login or password",
+            )),
+            Err(e) => {
+                error!("Can't verify password of '{}': {}", login, e);
+                Ok(AuthResult::failure(
+                    AuthFailure::WrongPassword,
+                    "Wrong login or password",
+                ))
+            }
+        }
+    }
+
+    async fn get_entry(&self, uuid: &Uuid) -> Result<Entry> {
+        let user = self
+            .read()
+            .users
+            .iter()
+            .find(|user| &user.uuid == uuid)
+            .cloned();
+        match user {
+            Some(user) => Ok(self.create_entry(&user)),
+            None => Err(EntryNotFound.into()),
+        }
+    }
+
+    async fn get_entry_from_name(&self, username: &str) -> Result<Entry> {
+        match self.find_user(username) {
+            Some(user) => Ok(self.create_entry(&user)),
+            None => Err(EntryNotFound.into()),
+        }
+    }
+
+    async fn update_access_token(&self, uuid: &Uuid, token: &str) -> Result<()> {
+        self.update_session(uuid, |session| {
+            session.access_token = Some(token.to_string())
+        })
+        .await
+    }
+
+    async fn update_server_id(&self, uuid: &Uuid, server_id: &str) -> Result<()> {
+        self.update_session(uuid, |session| {
+            session.server_id = Some(server_id.to_string())
+        })
+        .await
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+use uuid::Uuid;
+
+use crate::config::auth::password::constant_time_eq;
+use crate::config::auth::{AuthFailure, AuthProvide, AuthResult, Entry, EntryNotFound};
+use crate::config::OfflineAuthProvider;
+
+#[derive(Clone)]
+pub struct OfflineSession {
+    username: String,
+    access_token: Option<String>,
+    server_id: Option<String>,
+}
+
+// Same as java UUID.nameUUIDFromBytes("OfflinePlayer:" + name) used by vanilla offline mode
+pub fn offline_uuid(username: &str) -> Uuid {
+    let mut bytes = md5::compute(format!("OfflinePlayer:{}", username)).0;
+    bytes[6] = (bytes[6] & 0x0f) | 0x30;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    Uuid::from_bytes(bytes)
+}
+
+fn is_valid_username(username: &str) -> bool {
+    (3..=16).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl OfflineAuthProvider {
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<Uuid, OfflineSession>> {
+        self.sessions.read().expect("Offline sessions are poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<Uuid, OfflineSession>> {
+        self.sessions
+            .write()
+            .expect("Offline sessions are poisoned")
+    }
+
+    fn create_entry(&self, uuid: Uuid, username: &str) -> Entry {
+        match self.read().get(&uuid) {
+            Some(session) => Entry {
+                access_token: session.access_token.clone(),
+                server_id: session.server_id.clone(),
+                uuid,
+                username: session.username.clone(),
+            },
+            None => Entry {
+                access_token: None,
+                server_id: None,
+                uuid,
+                username: username.to_string(),
+            },
+        }
+    }
+
+    fn update_session<F>(&self, uuid: &Uuid, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut OfflineSession),
+    {
+        match self.write().get_mut(uuid) {
+            Some(session) => {
+                update(session);
+                Ok(())
+            }
+            None => Err(EntryNotFound.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvide for OfflineAuthProvider {
+    fn init(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn auth(&self, login: &str, password: &str, _ip: &str) -> Result<AuthResult> {
+        if !is_valid_username(login) {
+            return Ok(AuthResult::failure(
+                AuthFailure::NotFound,
+                "Username must contain 3-16 latin letters, digits or '_'",
+            ));
+        }
+        let wrong_password = match &self.password {
+            Some(shared) => !constant_time_eq(shared.as_bytes(), password.as_bytes()),
+            None => false,
+        };
+        if wrong_password {
+            return Ok(AuthResult::failure(
+                AuthFailure::WrongPassword,
+                "Wrong password",
+            ));
+        }
+        let uuid = offline_uuid(login);
+        self.write().entry(uuid).or_insert_with(|| OfflineSession {
+            username: login.to_string(),
+            access_token: None,
+            server_id: None,
+        });
+        Ok(AuthResult::success(uuid))
+    }
+
+    async fn get_entry(&self, uuid: &Uuid) -> Result<Entry> {
+        let username = match self.read().get(uuid) {
+            Some(session) => session.username.clone(),
+            None => return Err(EntryNotFound.into()),
+        };
+        Ok(self.create_entry(*uuid, &username))
+    }
+
+    async fn get_entry_from_name(&self, username: &str) -> Result<Entry> {
+        if !is_valid_username(username) {
+            return Err(EntryNotFound.into());
+        }
+        Ok(self.create_entry(offline_uuid(username), username))
+    }
+
+    async fn update_access_token(&self, uuid: &Uuid, token: &str) -> Result<()> {
+        self.update_session(uuid, |session| {
+            session.access_token = Some(token.to_string())
+        })
+    }
+
+    async fn update_server_id(&self, uuid: &Uuid, server_id: &str) -> Result<()> {
+        self.update_session(uuid, |session| {
+            session.server_id = Some(server_id.to_string())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Same as UUID.nameUUIDFromBytes("OfflinePlayer:Notch".getBytes(UTF_8)) in vanilla server
+    #[test]
+    fn matches_vanilla_offline_uuid() {
+        assert_eq!(
+            offline_uuid("Notch").to_string(),
+            "b50ad385-829d-3141-a216-7e7d7539ba7f"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{Context, Result};
+use hmac::Hmac;
+use sha2::{Sha256, Sha512};
+
+// Hashes are slow by design, so they run on blocking pool instead of async workers
+pub async fn verify(password: &str, hash: &str) -> Result<bool> {
+    let (password, hash) = (password.to_string(), hash.to_string());
+    tokio::task::spawn_blocking(move || verify_blocking(&password, &hash)).await?
+}
+
+// Supported formats: bcrypt ($2a$, $2b$, $2y$), argon2 PHC ($argon2i$, $argon2id$)
+// and Django PBKDF2 (pbkdf2_sha256$<iterations>$<salt>$<base64 hash>)
+fn verify_blocking(password: &str, hash: &str) -> Result<bool> {
+    if hash.starts_with("$2a$") || hash.starts_with("$2b$") || hash.starts_with("$2y$") {
+        Ok(bcrypt::verify(password, hash)?)
+    } else if hash.starts_with("$argon2") {
+        Ok(argon2::verify_encoded(hash, password.as_bytes())?)
+    } else if hash.starts_with("pbkdf2_") {
+        verify_pbkdf2(password, hash)
+    } else {
+        Err(anyhow::anyhow!("Unknown password hash format"))
+    }
+}
+
+// Used by console commands, which are not async
+pub fn hash(password: &str) -> Result<String> {
+    Ok(tokio::task::block_in_place(|| {
+        bcrypt::hash(password, bcrypt::DEFAULT_COST)
+    })?)
+}
+
+fn verify_pbkdf2(password: &str, hash: &str) -> Result<bool> {
+    let parts: Vec<&str> = hash.split('$').collect();
+    let (algorithm, iterations, salt, expected) = match parts.as_slice() {
+        [algorithm, iterations, salt, expected] => (*algorithm, *iterations, *salt, *expected),
+        _ => return Err(anyhow::anyhow!("Invalid PBKDF2 hash")),
+    };
+    let iterations: u32 = iterations
+        .parse()
+        .context("Invalid PBKDF2 iteration count")?;
+    if iterations == 0 {
+        return Err(anyhow::anyhow!("Invalid PBKDF2 iteration count"));
+    }
+    let expected = base64::decode(expected).context("Invalid PBKDF2 hash encoding")?;
+    // Otherwise truncated hash would match almost any password
+    if expected.len() < 16 {
+        return Err(anyhow::anyhow!("PBKDF2 hash is too short"));
+    }
+    let mut actual = vec![0u8; expected.len()];
+    match algorithm {
+        "pbkdf2_sha256" => pbkdf2::pbkdf2::<Hmac<Sha256>>(
+            password.as_bytes(),
+            salt.as_bytes(),
+            iterations,
+            &mut actual,
+        ),
+        "pbkdf2_sha512" => pbkdf2::pbkdf2::<Hmac<Sha512>>(
+            password.as_bytes(),
+            salt.as_bytes(),
+            iterations,
+            &mut actual,
+        ),
+        _ => return Err(anyhow::anyhow!("Unknown PBKDF2 algorithm: {}", algorithm)),
+    }
+    Ok(constant_time_eq(&actual, &expected))
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_bcrypt() {
+        let hash = "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW";
+        assert!(verify_blocking("U*U", hash).unwrap());
+        assert!(!verify_blocking("U*V", hash).unwrap());
+    }
+
+    #[test]
+    fn verifies_argon2() {
+        let hash =
+            "$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweHKfbftvj+qf+YFY4NeBbtA";
+        assert!(verify_blocking("password", hash).unwrap());
+        assert!(!verify_blocking("passwore", hash).unwrap());
+    }
+
+    #[test]
+    fn verifies_django_pbkdf2() {
+        let sha256 = "pbkdf2_sha256$1000$seasalt$YIWkt6M1JFXrHg5s0jZjBSc7C2Cz6QvchSJ0h8Y+i7c=";
+        assert!(verify_blocking("password", sha256).unwrap());
+        assert!(!verify_blocking("passwore", sha256).unwrap());
+        let sha512 = "pbkdf2_sha512$1000$seasalt$TCtBOEq+xXm+XNZRLfJoHDneh4iBM8b625BLGQLietyIGZ27aNH0oiYQ1uvtIKO2f60DdYIkgYbHnIAbnk1sWw==";
+        assert!(verify_blocking("password", sha512).unwrap());
+    }
+
+    #[test]
+    fn rejects_weak_pbkdf2() {
+        assert!(verify_blocking("password", "pbkdf2_sha256$1000$seasalt$").is_err());
+        assert!(verify_blocking("password", "pbkdf2_sha256$1000$seasalt$YIWk").is_err());
+        assert!(verify_blocking(
+            "password",
+            "pbkdf2_sha256$0$seasalt$YIWkt6M1JFXrHg5s0jZjBSc7C2Cz6QvchSJ0h8Y+i7c="
+        )
+        .is_err());
+        assert!(verify_blocking("password", "sha1$seasalt$abc").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use log::error;
+use sqlx::any::{AnyPool, AnyRow};
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::config::auth::{password, AuthFailure, AuthProvide, AuthResult, Entry, EntryNotFound};
+use crate::config::SqlAuthProvider;
+
+impl SqlAuthProvider {
+    fn pool(&self) -> &AnyPool {
+        self.pool
+            .as_ref()
+            .expect("Sql auth provider is not initialized")
+    }
+
+    fn get_query(query: &Option<String>) -> &str {
+        query
+            .as_deref()
+            .expect("Sql auth provider is not initialized")
+    }
+
+    fn placeholder(&self, index: usize) -> String {
+        if self.url.starts_with("postgres") {
+            format!("${}", index)
+        } else {
+            "?".to_string()
+        }
+    }
+
+    fn select_query(&self, column: &str) -> String {
+        let table = &self.table;
+        format!(
+            "SELECT {}, {}, {}, {}, {} FROM {} WHERE {} = {}",
+            table.uuid_column,
+            table.username_column,
+            table.password_column,
+            table.access_token_column,
+            table.server_id_column,
+            table.name,
+            column,
+            self.placeholder(1)
+        )
+    }
+
+    fn update_query(&self, column: &str) -> String {
+        format!(
+            "UPDATE {} SET {} = {} WHERE {} = {}",
+            self.table.name,
+            column,
+            self.placeholder(1),
+            self.table.uuid_column,
+            self.placeholder(2)
+        )
+    }
+
+    fn get_uuid(&self, row: &AnyRow) -> Result<Uuid> {
+        let uuid: String = row.try_get(self.table.uuid_column.as_str())?;
+        Uuid::parse_str(&uuid).with_context(|| format!("Invalid uuid in database: {}", uuid))
+    }
+
+    fn create_entry(&self, row: &AnyRow) -> Result<Entry> {
+        Ok(Entry {
+            access_token: row.try_get(self.table.access_token_column.as_str())?,
+            server_id: row.try_get(self.table.server_id_column.as_str())?,
+            uuid: self.get_uuid(row)?,
+            username: row.try_get(self.table.username_column.as_str())?,
+        })
+    }
+
+    async fn fetch(&self, query: &Option<String>, value: &str) -> Result<Option<AnyRow>> {
+        Ok(sqlx::query(SqlAuthProvider::get_query(query))
+            .bind(value)
+            .fetch_optional(self.pool())
+            .await?)
+    }
+
+    async fn update(&self, query: &Option<String>, uuid: &Uuid, value: &str) -> Result<()> {
+        let result = sqlx::query(SqlAuthProvider::get_query(query))
+            .bind(value)
+            .bind(uuid.to_string())
+            .execute(self.pool())
+            .await?;
+        if result.rows_affected() == 0 {
+            Err(EntryNotFound.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvide for SqlAuthProvider {
+    fn init(&mut self) -> Result<()> {
+        if self.queries.select_by_username.is_none() {
+            self.queries.select_by_username = Some(self.select_query(&self.table.username_column));
+        }
+        if self.queries.select_by_uuid.is_none() {
+            self.queries.select_by_uuid = Some(self.select_query(&self.table.uuid_column));
+        }
+        if self.queries.update_access_token.is_none() {
+            self.queries.update_access_token =
+                Some(self.update_query(&self.table.access_token_column));
+        }
+        if self.queries.update_server_id.is_none() {
+            self.queries.update_server_id = Some(self.update_query(&self.table.server_id_column));
+        }
+        self.pool = Some(AnyPool::connect_lazy(&self.url)?);
+        Ok(())
+    }
+
+    async fn auth(&self, login: &str, password: &str, _ip: &str) -> Result<AuthResult> {
+        let row = match self.fetch(&self.queries.select_by_username, login).await? {
+            Some(row) => row,
+            None => {
+                return Ok(AuthResult::failure(
+                    AuthFailure::NotFound,
+                    "Wrong login or password",
+                ))
+            }
+        };
+        let hash: String = row.try_get(self.table.password_column.as_str())?;
+        match password::verify(password, &hash).await {
+            Ok(true) => Ok(AuthResult::success(self.get_uuid(&row)?)),
+            Ok(false) => Ok(AuthResult::failure(
+                AuthFailure::WrongPassword,
+                "Wrong login or password",
+            )),
+            Err(e) => {
+                error!("Can't verify password of '{}': {}", login, e);
+                Ok(AuthResult::failure(
+                    AuthFailure::WrongPassword,
+                    "Wrong login or password",
+                ))
+            }
+        }
+    }
+
+    async fn get_entry(&self, uuid: &Uuid) -> Result<Entry> {
+        match self
+            .fetch(&self.queries.select_by_uuid, &uuid.to_string())
+            .await?
+        {
+            Some(row) => self.create_entry(&row),
+            None => Err(EntryNotFound.into()),
+        }
+    }
+
+    async fn get_entry_from_name(&self, username: &str) -> Result<Entry> {
+        match self
+            .fetch(&self.queries.select_by_username, username)
+            .await?
+        {
+            Some(row) => self.create_entry(&row),
+            None => Err(EntryNotFound.into()),
+        }
+    }
+
+    async fn update_access_token(&self, uuid: &Uuid, token: &str) -> Result<()> {
+        self.update(&self.queries.update_access_token, uuid, token)
+            .await
+    }
+
+    async fn update_server_id(&self, uuid: &Uuid, server_id: &str) -> Result<()> {
+        self.update(&self.queries.update_server_id, uuid, server_id)
+            .await
+    }
+}
@@ -1,40 +1,74 @@
 use serde_json::Value;
 use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 use strfmt::Format;
 use uuid::Uuid;
 
 use crate::config::auth::Entry;
 use crate::config::TextureProvider;
+use crate::textures::TextureStorage;
 
 impl TextureProvider {
-    pub fn get_skin_url(&self, entry: &Entry) -> Option<String> {
+    fn format_url(template: &Option<String>, entry: &Entry) -> Option<String> {
         let mut vars = HashMap::new();
         vars.insert("username".to_string(), entry.username.to_string());
         vars.insert("uuid".to_string(), entry.uuid.to_string());
-        Some((&self.skin_url.format(&vars).unwrap()).to_string())
+        Some(template.as_ref()?.format(&vars).unwrap())
+    }
+
+    pub fn get_skin_url(&self, entry: &Entry) -> Option<String> {
+        TextureProvider::format_url(&self.skin_url, entry)
     }
 
     pub fn get_cape_url(&self, entry: &Entry) -> Option<String> {
-        let mut vars = HashMap::new();
-        vars.insert("username".to_string(), entry.username.to_string());
-        vars.insert("uuid".to_string(), entry.uuid.to_string());
-        Some((&self.cape_url.format(&vars).unwrap()).to_string())
+        TextureProvider::format_url(&self.cape_url, entry)
     }
 
-    pub fn get_textures_property(&self, entry: &Entry) -> Value {
-        let timestamp = 0;
+    // Uploaded textures are preferred, skin template is emitted otherwise
+    // Cape template is used only without storage, missing CAPE means user has no cape
+    pub fn get_textures_property(&self, entry: &Entry, storage: &TextureStorage) -> Value {
+        let stored = storage.get(&entry.uuid);
+        let mut textures = serde_json::Map::new();
+        match stored.skin {
+            Some(skin) if skin.slim => {
+                textures.insert(
+                    "SKIN".to_string(),
+                    serde_json::json!({
+                        "url": storage.get_url(&skin.hash),
+                        "metadata": { "model": "slim" }
+                    }),
+                );
+            }
+            Some(skin) => {
+                textures.insert(
+                    "SKIN".to_string(),
+                    serde_json::json!({ "url": storage.get_url(&skin.hash) }),
+                );
+            }
+            None => {
+                if let Some(url) = self.get_skin_url(entry) {
+                    textures.insert("SKIN".to_string(), serde_json::json!({ "url": url }));
+                }
+            }
+        }
+        // Storage knows who has a cape, template would give it to every user
+        let cape_url = match stored.cape {
+            Some(cape) => Some(storage.get_url(&cape.hash)),
+            None if storage.is_enabled() => None,
+            None => self.get_cape_url(entry),
+        };
+        if let Some(url) = cape_url {
+            textures.insert("CAPE".to_string(), serde_json::json!({ "url": url }));
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis() as u64)
+            .unwrap_or_default();
         serde_json::json!({
             "timestamp": timestamp,
             "profileId": entry.uuid.to_simple().encode_lower(&mut Uuid::encode_buffer()),
             "profileName": entry.username,
-            "textures": {
-                "SKIN": {
-                    "url": self.get_skin_url(entry)
-                },
-                "CAPE": {
-                    "url": self.get_cape_url(entry)
-                }
-            }
+            "textures": textures
         })
     }
 }
@@ -0,0 +1,64 @@
+use anyhow::{Context, Result};
+use launcher_api::profile::Profile;
+use launcher_api::validation::RelativePath;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+pub mod gravit;
+pub mod mojang;
+
+#[derive(Default)]
+pub struct ImportReport {
+    pub missing_libraries: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+pub fn get_profile_dir(name: &str) -> Result<PathBuf> {
+    let name = RelativePath::new(name)?;
+    if name.as_str().contains('/') {
+        return Err(anyhow::anyhow!("Profile name can't contain '/'"));
+    }
+    let dir = Path::new("static/profiles").join(name.to_path());
+    if dir.join("profile.json").exists() {
+        return Err(anyhow::anyhow!("Profile {:?} already exists", dir));
+    }
+    Ok(dir)
+}
+
+pub fn save_profile(profile: &Profile) -> Result<PathBuf> {
+    let dir = get_profile_dir(&profile.name)?;
+    let path = dir.join("profile.json");
+    fs::create_dir_all(&dir)?;
+    serde_json::to_writer_pretty(File::create(&path)?, profile)
+        .with_context(|| format!("Can't write profile {:?}", path))?;
+    Ok(path)
+}
+
+pub fn find_missing_libraries(profile: &Profile) -> Vec<String> {
+    profile
+        .libraries
+        .iter()
+        .map(|library| library.path())
+        .filter(|path| !Path::new("static/libraries").join(path).is_file())
+        .map(String::from)
+        .collect()
+}
+
+// Imported lists are relative to client dir, ours to launcher game dir
+pub fn map_client_path(name: &str, entry: &str, report: &mut ImportReport) -> Option<String> {
+    match RelativePath::new(format!("profiles/{}/{}", name, entry.trim_end_matches('/'))) {
+        Ok(path) => Some(path.to_string()),
+        Err(e) => {
+            report
+                .skipped
+                .push(format!("Update entry '{}' is invalid: {}", entry, e));
+            None
+        }
+    }
+}
+
+// Assets are downloaded to the same path as on server: assets/<index>
+pub fn get_assets_dir(assets: &str) -> String {
+    format!("assets/{}", assets)
+}
@@ -0,0 +1,220 @@
+use super::{get_assets_dir, get_profile_dir, map_client_path, ImportReport};
+use anyhow::{Context, Result};
+use launcher_api::profile::{Argument, Library, Profile, PROFILE_SCHEMA_VERSION};
+use path_slash::PathExt;
+use serde::Deserialize;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use walkdir::WalkDir;
+
+// Gravit keeps libraries and natives inside client dir, we share them between profiles
+const SHARED_DIRS: [&str; 2] = ["libraries", "natives"];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GravitProfile {
+    title: String,
+    version: String,
+    dir: String,
+    asset_index: String,
+    asset_dir: String,
+    main_class: String,
+    info: Option<String>,
+    #[serde(default)]
+    update_verify: Vec<String>,
+    #[serde(default)]
+    update_exclusions: Vec<String>,
+    #[serde(default)]
+    class_path: Vec<String>,
+    #[serde(default)]
+    jvm_args: Vec<String>,
+    #[serde(default)]
+    client_args: Vec<String>,
+    #[serde(default)]
+    servers: Vec<GravitServer>,
+    server_address: Option<String>,
+    server_port: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GravitServer {
+    server_address: String,
+    server_port: u32,
+    #[serde(default)]
+    is_default: bool,
+}
+
+pub fn read_profile(path: &Path) -> Result<GravitProfile> {
+    serde_json::from_reader(File::open(path)?)
+        .with_context(|| format!("Can't parse Gravit profile {:?}", path))
+}
+
+pub fn import_profile(
+    path: &Path,
+    updates: &Path,
+    name: Option<&str>,
+) -> Result<(Profile, ImportReport)> {
+    let gravit = read_profile(path)?;
+    let name = name.unwrap_or(&gravit.title).to_string();
+    let profile_dir = get_profile_dir(&name)?;
+    let client_dir = updates.join(&gravit.dir);
+    let assets_dir = updates.join(&gravit.asset_dir);
+    if !client_dir.is_dir() {
+        return Err(anyhow::anyhow!("Client dir {:?} doesn't exist", client_dir));
+    }
+    let mut report = ImportReport::default();
+
+    let libraries = copy_dir(
+        &client_dir.join("libraries"),
+        Path::new("static/libraries"),
+        &[],
+        &mut report,
+    )?;
+    copy_dir(
+        &client_dir.join("natives"),
+        &Path::new("static/natives").join(&gravit.version),
+        &[],
+        &mut report,
+    )?;
+    copy_dir(&client_dir, &profile_dir, &SHARED_DIRS, &mut report)?;
+    copy_dir(
+        &assets_dir,
+        &Path::new("static/assets").join(&gravit.asset_index),
+        &[],
+        &mut report,
+    )?;
+    if let Some(info) = &gravit.info {
+        fs::write(profile_dir.join("description.txt"), info)?;
+    }
+
+    let profile = convert_profile(gravit, name, libraries, &client_dir, &mut report);
+    Ok((profile, report))
+}
+
+pub fn convert_profile(
+    gravit: GravitProfile,
+    name: String,
+    libraries: Vec<String>,
+    client_dir: &Path,
+    report: &mut ImportReport,
+) -> Profile {
+    let mut class_path = Vec::new();
+    for entry in &gravit.class_path {
+        if SHARED_DIRS.contains(&entry.as_str()) {
+            continue;
+        }
+        let path = client_dir.join(entry);
+        if path.is_dir() {
+            class_path.extend(
+                list_files(&path)
+                    .into_iter()
+                    .map(|file| format!("{}/{}", entry, file)),
+            );
+        } else {
+            class_path.push(entry.clone());
+        }
+    }
+    let servers = &gravit.servers;
+    let (server_name, server_port) = match servers
+        .iter()
+        .find(|server| server.is_default)
+        .or_else(|| servers.first())
+    {
+        Some(server) => (server.server_address.clone(), server.server_port),
+        None => (
+            gravit
+                .server_address
+                .clone()
+                .unwrap_or_else(|| "localhost".to_string()),
+            gravit.server_port.unwrap_or(25565),
+        ),
+    };
+    let version = &gravit.version;
+    let update_verify = gravit
+        .update_verify
+        .iter()
+        .filter_map(|entry| match entry.as_str() {
+            "libraries" => None,
+            "natives" => Some(format!("natives/{}", version)),
+            entry => map_client_path(&name, entry, report),
+        })
+        .collect();
+    let update_exclusion = gravit
+        .update_exclusions
+        .iter()
+        .filter_map(|entry| map_client_path(&name, entry, report))
+        .collect();
+
+    let assets_dir = get_assets_dir(&gravit.asset_index);
+
+    Profile {
+        schema_version: PROFILE_SCHEMA_VERSION,
+        name,
+        extends: None,
+        version: gravit.version,
+        libraries: libraries.into_iter().map(Library::Path).collect(),
+        class_path,
+        main_class: gravit.main_class.replace('.', "/"),
+        update_verify,
+        update_exclusion,
+        jvm_args: gravit.jvm_args.into_iter().map(Argument::Value).collect(),
+        client_args: gravit
+            .client_args
+            .into_iter()
+            .map(Argument::Value)
+            .collect(),
+        assets: gravit.asset_index,
+        assets_dir,
+        server_name,
+        server_port,
+    }
+}
+
+fn list_files(dir: &Path) -> Vec<String> {
+    WalkDir::new(dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.path().strip_prefix(dir).ok().map(|p| p.to_slash_lossy()))
+        .collect()
+}
+
+// Shared files that already exist are kept, so other profiles don't break
+fn copy_dir(
+    from: &Path,
+    to: &Path,
+    skip: &[&str],
+    report: &mut ImportReport,
+) -> Result<Vec<String>> {
+    if !from.is_dir() {
+        report
+            .skipped
+            .push(format!("Directory {:?} doesn't exist", from));
+        return Ok(Vec::new());
+    }
+    let mut copied = Vec::new();
+    for file in list_files(from) {
+        if skip.iter().any(|dir| file.split('/').next() == Some(*dir)) {
+            continue;
+        }
+        let source = from.join(&file);
+        let target = to.join(&file);
+        if target.exists() {
+            if fs::metadata(&target)?.len() != fs::metadata(&source)?.len() {
+                report
+                    .skipped
+                    .push(format!("File {:?} already exists and differs", target));
+            }
+        } else {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&source, &target).with_context(|| format!("Can't copy {:?}", source))?;
+        }
+        copied.push(file);
+    }
+    Ok(copied)
+}
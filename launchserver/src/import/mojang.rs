@@ -0,0 +1,455 @@
+use super::{get_assets_dir, map_client_path, ImportReport};
+use anyhow::{Context, Result};
+use launcher_api::profile::{
+    Argument, ArgumentValue, Library, OsRule, Profile, Rule, RuleAction, PROFILE_SCHEMA_VERSION,
+};
+use launcher_api::validation::{Arch, OsName};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+// Launcher passes these values itself, so arguments using them are dropped silently
+const LAUNCHER_VARIABLES: [&str; 10] = [
+    "auth_player_name",
+    "auth_uuid",
+    "auth_access_token",
+    "auth_session",
+    "game_directory",
+    "game_assets",
+    "assets_root",
+    "assets_index_name",
+    "natives_directory",
+    "classpath",
+];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Version {
+    id: String,
+    inherits_from: Option<String>,
+    main_class: Option<String>,
+    #[serde(default)]
+    libraries: Vec<VersionLibrary>,
+    asset_index: Option<AssetIndex>,
+    assets: Option<String>,
+    minecraft_arguments: Option<String>,
+    #[serde(default)]
+    arguments: Arguments,
+}
+
+#[derive(Deserialize)]
+struct AssetIndex {
+    id: String,
+}
+
+#[derive(Deserialize, Default)]
+struct Arguments {
+    #[serde(default)]
+    game: Vec<VersionArgument>,
+    #[serde(default)]
+    jvm: Vec<VersionArgument>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VersionArgument {
+    Value(String),
+    Conditional {
+        value: ArgumentValue,
+        #[serde(default)]
+        rules: Vec<VersionRule>,
+    },
+}
+
+#[derive(Deserialize)]
+struct VersionLibrary {
+    name: String,
+    #[serde(default)]
+    rules: Vec<VersionRule>,
+    downloads: Option<Downloads>,
+    natives: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct Downloads {
+    artifact: Option<Artifact>,
+}
+
+#[derive(Deserialize)]
+struct Artifact {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct VersionRule {
+    action: RuleAction,
+    os: Option<VersionOs>,
+    #[serde(default)]
+    features: HashMap<String, bool>,
+}
+
+#[derive(Deserialize)]
+struct VersionOs {
+    name: Option<String>,
+    arch: Option<String>,
+    version: Option<String>,
+}
+
+pub fn import_version(path: &Path, name: &str) -> Result<(Profile, ImportReport)> {
+    let version = read_version(path)?;
+    let mut report = ImportReport::default();
+    let variables: HashMap<&str, String> = [
+        ("version_name", version.id.clone()),
+        ("version_type", "release".to_string()),
+        ("user_type", "mojang".to_string()),
+        ("user_properties", "{}".to_string()),
+        ("launcher_name", "NSLauncher".to_string()),
+        ("launcher_version", env!("CARGO_PKG_VERSION").to_string()),
+    ]
+    .iter()
+    .cloned()
+    .collect();
+
+    let mut libraries = Vec::new();
+    for library in version.libraries {
+        match library_path(&library) {
+            Some(path) if library.rules.is_empty() => libraries.push(Library::Path(path)),
+            Some(path) => {
+                let item = format!("Library '{}'", library.name);
+                if let Some(rules) = convert_rules(library.rules, &item, &mut report) {
+                    libraries.push(Library::Conditional { path, rules });
+                }
+            }
+            None if library.natives.is_some() => report.skipped.push(format!(
+                "Library '{}' contains only natives, put them into static/natives/{}",
+                library.name, version.id
+            )),
+            None => report.skipped.push(format!(
+                "Library '{}' has unknown name format",
+                library.name
+            )),
+        }
+    }
+    let client_args = match version.minecraft_arguments {
+        Some(arguments) => convert_plain_arguments(
+            arguments.split_whitespace().map(String::from).collect(),
+            &variables,
+            &mut report,
+        ),
+        None => convert_arguments(version.arguments.game, &variables, &mut report),
+    };
+    let jvm_args = convert_arguments(version.arguments.jvm, &variables, &mut report);
+
+    let assets = version
+        .asset_index
+        .map(|index| index.id)
+        .or(version.assets)
+        .context("Version doesn't contain asset index")?;
+    let assets_dir = get_assets_dir(&assets);
+    let profile = Profile {
+        schema_version: PROFILE_SCHEMA_VERSION,
+        name: name.to_string(),
+        extends: None,
+        version: version.id,
+        libraries,
+        class_path: vec!["minecraft.jar".to_string()],
+        main_class: version
+            .main_class
+            .context("Version doesn't contain mainClass")?
+            .replace('.', "/"),
+        update_verify: map_client_path(name, "mods", &mut report)
+            .into_iter()
+            .collect(),
+        update_exclusion: Vec::new(),
+        jvm_args,
+        client_args,
+        assets,
+        assets_dir,
+        server_name: "localhost".to_string(),
+        server_port: 25565,
+    };
+    Ok((profile, report))
+}
+
+fn read_version(path: &Path) -> Result<Version> {
+    let mut version: Version = serde_json::from_reader(File::open(path)?)
+        .with_context(|| format!("Can't parse version {:?}", path))?;
+    if let Some(parent) = version.inherits_from.take() {
+        let parent = read_version(&find_parent(path, &parent)?)?;
+        version = merge(parent, version);
+    }
+    Ok(version)
+}
+
+// Parent is searched near child and in launcher versions layout: versions/<id>/<id>.json
+fn find_parent(path: &Path, id: &str) -> Result<PathBuf> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file_name = format!("{}.json", id);
+    [
+        dir.join(&file_name),
+        dir.join("..").join(id).join(&file_name),
+    ]
+    .iter()
+    .find(|path| path.is_file())
+    .cloned()
+    .with_context(|| format!("Can't find parent version '{}' for {:?}", id, path))
+}
+
+// Natives and assets are stored by game version, so parent id is kept
+fn merge(parent: Version, child: Version) -> Version {
+    let mut libraries = child.libraries;
+    for library in parent.libraries {
+        let key = library_key(&library.name);
+        if libraries
+            .iter()
+            .all(|child| library_key(&child.name) != key)
+        {
+            libraries.push(library);
+        }
+    }
+    let mut arguments = parent.arguments;
+    arguments.game.extend(child.arguments.game);
+    arguments.jvm.extend(child.arguments.jvm);
+    Version {
+        id: parent.id,
+        inherits_from: None,
+        main_class: child.main_class.or(parent.main_class),
+        libraries,
+        asset_index: child.asset_index.or(parent.asset_index),
+        assets: child.assets.or(parent.assets),
+        minecraft_arguments: child.minecraft_arguments.or(parent.minecraft_arguments),
+        arguments,
+    }
+}
+
+// Maven coordinates without version: group:artifact[:classifier]
+fn library_key(name: &str) -> String {
+    let parts: Vec<&str> = name.split(':').collect();
+    match parts.as_slice() {
+        [group, artifact, _, classifier] => format!("{}:{}:{}", group, artifact, classifier),
+        [group, artifact, ..] => format!("{}:{}", group, artifact),
+        _ => name.to_string(),
+    }
+}
+
+fn library_path(library: &VersionLibrary) -> Option<String> {
+    if let Some(artifact) = library
+        .downloads
+        .as_ref()
+        .and_then(|downloads| downloads.artifact.as_ref())
+    {
+        return Some(artifact.path.clone());
+    }
+    if library.natives.is_some() {
+        return None;
+    }
+    let parts: Vec<&str> = library.name.split(':').collect();
+    match parts.as_slice() {
+        [group, artifact, version] => Some(format!(
+            "{}/{}/{}/{}-{}.jar",
+            group.replace('.', "/"),
+            artifact,
+            version,
+            artifact,
+            version
+        )),
+        [group, artifact, version, classifier] => Some(format!(
+            "{}/{}/{}/{}-{}-{}.jar",
+            group.replace('.', "/"),
+            artifact,
+            version,
+            artifact,
+            version,
+            classifier
+        )),
+        _ => None,
+    }
+}
+
+// Item with os.version rule is skipped, otherwise it would be allowed for every OS version
+fn convert_rules(
+    rules: Vec<VersionRule>,
+    item: &str,
+    report: &mut ImportReport,
+) -> Option<Vec<Rule>> {
+    if rules
+        .iter()
+        .any(|rule| matches!(&rule.os, Some(os) if os.version.is_some()))
+    {
+        report
+            .skipped
+            .push(format!("{} depends on OS version, add it manually", item));
+        return None;
+    }
+    let mut converted = Vec::new();
+    for rule in rules {
+        let os = match rule.os {
+            Some(os) => match convert_os(&os) {
+                Some(os) => Some(os),
+                None => {
+                    report.skipped.push(format!(
+                        "Rule for unknown os '{}' ({})",
+                        os.name.unwrap_or_default(),
+                        os.arch.unwrap_or_default()
+                    ));
+                    continue;
+                }
+            },
+            None => None,
+        };
+        converted.push(Rule {
+            action: rule.action,
+            os,
+            features: rule.features,
+        });
+    }
+    Some(converted)
+}
+
+// Newer versions use names like "osx-arm64" instead of separate arch field
+fn convert_os(os: &VersionOs) -> Option<OsRule> {
+    let (name, name_arch) = match &os.name {
+        Some(name) => {
+            let mut parts = name.splitn(2, '-');
+            let os_name = match parts.next() {
+                Some("linux") => OsName::Linux,
+                Some("osx") | Some("macos") => OsName::MacOS,
+                Some("windows") => OsName::Windows,
+                _ => return None,
+            };
+            let arch = match parts.next() {
+                Some(arch) => Some(convert_arch(arch)?),
+                None => None,
+            };
+            (Some(os_name), arch)
+        }
+        None => (None, None),
+    };
+    let arch = match &os.arch {
+        Some(arch) => Some(convert_arch(arch)?),
+        None => name_arch,
+    };
+    Some(OsRule {
+        name,
+        arch,
+        version: None,
+    })
+}
+
+fn convert_arch(arch: &str) -> Option<Arch> {
+    match arch {
+        "x86" => Some(Arch::X86),
+        "x64" | "x86_64" | "amd64" => Some(Arch::X64),
+        "arm64" | "aarch64" => Some(Arch::Arm64),
+        _ => None,
+    }
+}
+
+fn convert_arguments(
+    arguments: Vec<VersionArgument>,
+    variables: &HashMap<&str, String>,
+    report: &mut ImportReport,
+) -> Vec<Argument> {
+    let mut converted = Vec::new();
+    let mut plain = Vec::new();
+    for argument in arguments {
+        match argument {
+            VersionArgument::Value(value) => plain.push(value),
+            VersionArgument::Conditional { value, rules } => {
+                converted.extend(convert_plain_arguments(
+                    plain.split_off(0),
+                    variables,
+                    report,
+                ));
+                let values = match value {
+                    ArgumentValue::Single(value) => vec![value],
+                    ArgumentValue::Multiple(values) => values,
+                };
+                let item = format!("Argument '{}'", values.join(" "));
+                let rules = match convert_rules(rules, &item, report) {
+                    Some(rules) => rules,
+                    None => continue,
+                };
+                match substitute_all(&values, variables) {
+                    Ok(mut resolved) => converted.push(Argument::Conditional {
+                        value: if resolved.len() == 1 {
+                            ArgumentValue::Single(resolved.remove(0))
+                        } else {
+                            ArgumentValue::Multiple(resolved)
+                        },
+                        rules,
+                    }),
+                    Err(variable) => skip_argument(&values, &variable, report),
+                }
+            }
+        }
+    }
+    converted.extend(convert_plain_arguments(plain, variables, report));
+    converted
+}
+
+// Option and its value are kept or dropped together
+fn convert_plain_arguments(
+    arguments: Vec<String>,
+    variables: &HashMap<&str, String>,
+    report: &mut ImportReport,
+) -> Vec<Argument> {
+    let mut converted = Vec::new();
+    let mut i = 0;
+    while i < arguments.len() {
+        let has_value = arguments[i].starts_with('-')
+            && matches!(arguments.get(i + 1), Some(next) if !next.starts_with('-'));
+        let group = &arguments[i..if has_value { i + 2 } else { i + 1 }];
+        match substitute_all(group, variables) {
+            Ok(resolved) => converted.extend(resolved.into_iter().map(Argument::Value)),
+            Err(variable) => skip_argument(group, &variable, report),
+        }
+        i += group.len();
+    }
+    converted
+}
+
+fn skip_argument(values: &[String], variable: &str, report: &mut ImportReport) {
+    if !LAUNCHER_VARIABLES.contains(&variable) {
+        report.skipped.push(format!(
+            "Argument '{}' uses unknown variable '{}'",
+            values.join(" "),
+            variable
+        ));
+    }
+}
+
+fn substitute_all(
+    values: &[String],
+    variables: &HashMap<&str, String>,
+) -> std::result::Result<Vec<String>, String> {
+    values
+        .iter()
+        .map(|value| substitute(value, variables))
+        .collect()
+}
+
+fn substitute(
+    value: &str,
+    variables: &HashMap<&str, String>,
+) -> std::result::Result<String, String> {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let variable = &rest[start + 2..end];
+        result.push_str(&rest[..start]);
+        result.push_str(
+            variables
+                .get(variable)
+                .ok_or_else(|| variable.to_string())?,
+        );
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
@@ -9,19 +9,28 @@ use tokio::sync::RwLock;
 
 use crate::config::Config;
 use crate::security::SecurityManager;
+use crate::server::limiter::AuthLimiter;
+use crate::session::SessionManager;
+use crate::textures::TextureStorage;
 
 mod bundle;
 mod commands;
 mod config;
+mod import;
 mod logger;
 mod security;
 mod server;
+mod session;
+mod textures;
 
 pub struct LaunchServer {
     pub config: Config,
     pub security: SecurityManager,
     pub profiles: HashMap<String, Profile>,
     pub profiles_info: Vec<ProfileInfo>,
+    pub auth_limiter: AuthLimiter,
+    pub sessions: SessionManager,
+    pub textures: Arc<TextureStorage>,
 }
 
 impl LaunchServer {
@@ -36,12 +45,18 @@ impl LaunchServer {
         let (profiles, profiles_info) = profile::get_profiles();
         let mut security = SecurityManager::default();
         security.rehash(profiles.values(), &[], config.file_server.clone());
+        let auth_limiter = AuthLimiter::new(&config.auth_limiter);
+        let sessions = SessionManager::new(&config.sessions);
+        let textures = Arc::new(TextureStorage::new(&config.texture.storage));
 
         LaunchServer {
             config,
             security,
             profiles,
             profiles_info,
+            auth_limiter,
+            sessions,
+            textures,
         }
     }
 }
@@ -49,6 +64,11 @@ impl LaunchServer {
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     let data = Arc::new(RwLock::new(LaunchServer::new().await));
-    tokio::join!(commands::start(data.clone()), server::start(data.clone()));
+    tokio::join!(
+        commands::start(data.clone()),
+        server::start(data.clone()),
+        session::start_cleanup(data.clone()),
+        session::start_saver(data.clone())
+    );
     Ok(())
 }
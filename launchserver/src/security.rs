@@ -1,19 +1,35 @@
 use anyhow::{Context, Result};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 use ecies_ed25519::SecretKey;
+use ed25519_dalek::{Keypair, Signer};
+use launcher_api::message::Signed;
 use launcher_api::profile::Profile;
-use launcher_api::validation::{OsType, RemoteDirectory, RemoteFile};
+use launcher_api::validation::{
+    Digest, HashAlgorithm, OsType, RelativePath, RemoteDirectory, RemoteFile,
+};
 use log::{error, info};
 use path_slash::{PathBufExt, PathExt};
 use rand::rngs::OsRng;
 use reqwest::Url;
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::{DecodePrivateKey, EncodePrivateKey, EncodePublicKey, LineEnding};
+use rsa::signature::{SignatureEncoding, Signer as _};
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use serde::Serialize;
+use serde_json::Value;
+use sha1::Sha1;
+use sha2::{Digest as _, Sha256};
 use std::collections::hash_map::Values;
 use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
 use std::io::SeekFrom;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
 use walkdir::{DirEntry, WalkDir};
 
 #[derive(PartialEq, Eq, Hash)]
@@ -22,15 +38,33 @@ pub struct NativeVersion {
     pub(crate) os_type: OsType,
 }
 
+#[derive(PartialEq, Eq, Hash)]
+pub struct ProfileOs {
+    pub(crate) profile: String,
+    pub(crate) os_type: OsType,
+}
+
 pub struct SecurityManager {
     pub secret_key: SecretKey,
+    pub sign_key: Keypair,
+    pub texture_key: SigningKey<Sha1>,
     pub profiles: Option<HashMap<String, RemoteDirectory>>,
-    pub libraries: Option<HashMap<String, RemoteDirectory>>,
+    pub libraries: Option<HashMap<ProfileOs, RemoteDirectory>>,
     pub assets: Option<HashMap<String, RemoteDirectory>>,
     pub natives: Option<HashMap<NativeVersion, RemoteDirectory>>,
     pub jres: Option<HashMap<OsType, RemoteDirectory>>,
+    signed_textures: Mutex<HashMap<Uuid, SignedTextures>>,
 }
 
+struct SignedTextures {
+    key: String,
+    value: String,
+    signature: String,
+}
+
+// Cache is dropped when it grows over this, entries are cheap to recreate
+const MAX_SIGNED_TEXTURES: usize = 10_000;
+
 impl Default for SecurityManager {
     fn default() -> Self {
         let public_key = Path::new("public_key");
@@ -44,13 +78,47 @@ impl Default for SecurityManager {
             .expect("Failed to get secret_key, try restart launch_server!")
             .read_to_end(&mut bytes)
             .expect("Failed to read secret_key, try delete it and restart launch_server!");
+        let sign_public_key = Path::new("sign_public_key");
+        let sign_secret_key = Path::new("sign_secret_key");
+        if !sign_public_key.exists() || !sign_secret_key.exists() {
+            info!("Creating new Ed25519 sign KeyPair...");
+            SecurityManager::create_sign_keys(sign_public_key, sign_secret_key)
+                .expect("Failed to create sign keys!");
+        }
+        let mut sign_bytes = Vec::new();
+        File::open("sign_secret_key")
+            .expect("Failed to get sign_secret_key, try restart launch_server!")
+            .read_to_end(&mut sign_bytes)
+            .expect("Failed to read sign_secret_key, try delete it and restart launch_server!");
+        let sign_secret =
+            ed25519_dalek::SecretKey::from_bytes(&sign_bytes).expect("Failed to parse sign key!");
+        let texture_public_key = Path::new("texture_public_key.pem");
+        let texture_secret_key = Path::new("texture_secret_key.pem");
+        if !texture_public_key.exists() || !texture_secret_key.exists() {
+            info!("Creating new RSA texture KeyPair...");
+            SecurityManager::create_texture_keys(texture_public_key, texture_secret_key)
+                .expect("Failed to create texture keys!");
+        }
+        let mut texture_pem = String::new();
+        File::open(texture_secret_key)
+            .expect("Failed to get texture_secret_key.pem, try restart launch_server!")
+            .read_to_string(&mut texture_pem)
+            .expect("Failed to read texture key, try delete it and restart launch_server!");
         SecurityManager {
             secret_key: SecretKey::from_bytes(&bytes).expect("Failed to parse key!"),
+            texture_key: SigningKey::new(
+                RsaPrivateKey::from_pkcs8_pem(&texture_pem).expect("Failed to parse texture key!"),
+            ),
+            sign_key: Keypair {
+                public: (&sign_secret).into(),
+                secret: sign_secret,
+            },
             profiles: None,
             libraries: None,
             assets: None,
             natives: None,
             jres: None,
+            signed_textures: Mutex::new(HashMap::new()),
         }
     }
 }
@@ -85,6 +153,10 @@ pub fn create_remote_file<P: AsRef<Path>>(path: P, file_server: String) -> Resul
         .into_string(),
         size: buffer.len() as u64,
         checksum: t1ha::t1ha2_atonce128(buffer.as_slice(), 1),
+        digest: Some(Digest {
+            algorithm: HashAlgorithm::Sha256,
+            hash: format!("{:x}", Sha256::digest(&buffer)),
+        }),
     })
 }
 
@@ -98,19 +170,79 @@ impl SecurityManager {
             .map_err(|_| anyhow::anyhow!("Password contains invalid symbols!"))?)
     }
 
+    pub fn sign<T: Serialize>(&self, value: &T) -> Result<Signed<T>> {
+        Ok(Signed::sign(value, |payload| {
+            self.sign_key.sign(payload).to_bytes().to_vec()
+        })?)
+    }
+
+    // Yggdrasil textures are signed with SHA1withRSA, returns base64 encoded value and signature
+    // rsa crate signing isn't constant-time (RUSTSEC-2023-0071), so signature is reused while
+    // textures don't change and clients can't collect signatures of many payloads
+    pub fn sign_textures(&self, uuid: &Uuid, property: &Value) -> Result<(String, String)> {
+        let key = serde_json::to_string(&(&property["profileName"], &property["textures"]))?;
+        let mut cache = self
+            .signed_textures
+            .lock()
+            .expect("Signed textures are poisoned");
+        if let Some(signed) = cache.get(uuid).filter(|signed| signed.key == key) {
+            return Ok((signed.value.clone(), signed.signature.clone()));
+        }
+        let value = base64::encode(property.to_string());
+        let signature = base64::encode(self.texture_key.try_sign(value.as_bytes())?.to_bytes());
+        if cache.len() >= MAX_SIGNED_TEXTURES {
+            cache.clear();
+        }
+        cache.insert(
+            *uuid,
+            SignedTextures {
+                key,
+                value: value.clone(),
+                signature: signature.clone(),
+            },
+        );
+        Ok((value, signature))
+    }
+
+    // DER encoded SubjectPublicKeyInfo, same format as yggdrasil_session_pubkey.der in authlib
+    pub fn get_texture_public_key(&self) -> Result<Vec<u8>> {
+        let public_key = RsaPublicKey::from(self.texture_key.as_ref());
+        Ok(public_key.to_public_key_der()?.into_vec())
+    }
+
+    fn create_texture_keys(public_key: &Path, secret_key: &Path) -> Result<()> {
+        let key = RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 4096)?;
+        let public_pem = key.to_public_key().to_public_key_pem(LineEnding::LF)?;
+        SecurityManager::create_key(public_key, public_pem.as_bytes(), false)?;
+        let secret_pem = key.to_pkcs8_pem(LineEnding::LF)?;
+        SecurityManager::create_key(secret_key, secret_pem.as_bytes(), true)?;
+        Ok(())
+    }
+
+    fn create_sign_keys(public_key: &Path, secret_key: &Path) -> Result<()> {
+        let keypair = Keypair::generate(&mut OsRng);
+        SecurityManager::create_key(public_key, &keypair.public.to_bytes(), false)?;
+        SecurityManager::create_key(secret_key, &keypair.secret.to_bytes(), true)?;
+        Ok(())
+    }
+
     fn create_keys(public_key: &Path, secret_key: &Path) -> Result<()> {
         let (secret, public) = ecies_ed25519::generate_keypair(&mut OsRng);
-        SecurityManager::create_key(public_key, public.to_bytes())?;
-        SecurityManager::create_key(secret_key, secret.to_bytes())?;
+        SecurityManager::create_key(public_key, &public.to_bytes(), false)?;
+        SecurityManager::create_key(secret_key, &secret.to_bytes(), true)?;
         Ok(())
     }
 
-    fn create_key(path: &Path, bytes: [u8; 32]) -> Result<()> {
-        OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path)?
-            .write_all(&bytes)?;
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn create_key(path: &Path, bytes: &[u8], secret: bool) -> Result<()> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // Secret keys are readable only by owner
+        #[cfg(unix)]
+        if secret {
+            options.mode(0o600);
+        }
+        options.open(path)?.write_all(bytes)?;
         Ok(())
     }
 
@@ -166,33 +298,50 @@ impl SecurityManager {
     fn hash_libraries(
         profiles: Values<String, Profile>,
         file_server: String,
-    ) -> Result<HashMap<String, RemoteDirectory>> {
+    ) -> Result<HashMap<ProfileOs, RemoteDirectory>> {
         let mut libs = HashMap::new();
         for file in get_files_from_dir("static/libraries") {
             libs.insert(
-                file.path().strip_prefix("static/")?.to_owned(),
+                RelativePath::from_path(file.path().strip_prefix("static/")?)?,
                 create_remote_file(file.path(), file_server.clone())?,
             );
         }
         let mut hashed_libs = HashMap::new();
 
         for profile in profiles {
-            let mut hashed_profile_libs = RemoteDirectory::new();
-            for lib in &profile.libraries {
-                let lib = PathBuf::from(format!("libraries/{}", lib));
-                match libs.get(&lib) {
-                    Some(file) => {
-                        hashed_profile_libs.insert(lib, file.clone());
+            let mut profile_libs = Vec::new();
+            for library in &profile.libraries {
+                let lib = match RelativePath::new(format!("libraries/{}", library.path())) {
+                    Ok(lib) => lib,
+                    Err(error) => {
+                        error!("Profile '{}' use invalid lib: {}!", profile.name, error);
+                        continue;
                     }
+                };
+                match libs.get(&lib) {
+                    Some(file) => profile_libs.push((library, lib, file)),
                     None => {
                         error!(
-                            "Profile '{}' use lib '{:?}' that doesn't exists in files!",
+                            "Profile '{}' use lib '{}' that doesn't exists in files!",
                             profile.name, lib
                         );
                     }
                 }
             }
-            hashed_libs.insert(profile.name.clone(), hashed_profile_libs);
+            for os_type in OsType::ALL.iter() {
+                let hashed_profile_libs: RemoteDirectory = profile_libs
+                    .iter()
+                    .filter(|(library, _, _)| library.is_allowed(os_type, None))
+                    .map(|(_, lib, file)| (lib.clone(), (*file).clone()))
+                    .collect();
+                hashed_libs.insert(
+                    ProfileOs {
+                        profile: profile.name.clone(),
+                        os_type: os_type.clone(),
+                    },
+                    hashed_profile_libs,
+                );
+            }
         }
         Ok(hashed_libs)
     }
@@ -217,9 +366,12 @@ impl SecurityManager {
             let mut hashed_native: HashMap<OsType, RemoteDirectory> = [
                 (OsType::LinuxX64, RemoteDirectory::new()),
                 (OsType::LinuxX32, RemoteDirectory::new()),
+                (OsType::LinuxArm64, RemoteDirectory::new()),
                 (OsType::MacOSX64, RemoteDirectory::new()),
+                (OsType::MacOSArm64, RemoteDirectory::new()),
                 (OsType::WindowsX64, RemoteDirectory::new()),
                 (OsType::WindowsX32, RemoteDirectory::new()),
+                (OsType::WindowsArm64, RemoteDirectory::new()),
             ]
             .iter()
             .cloned()
@@ -231,42 +383,26 @@ impl SecurityManager {
 
                 let extension = path.extension().and_then(OsStr::to_str);
                 if let Some(extension) = extension {
-                    let mut os_type = None;
-                    if extension.eq("dll") {
-                        let mut file = File::open(path)?;
-                        file.seek(SeekFrom::Start(0x3C))?;
-                        let pe_header = file.read_u32::<LittleEndian>()?;
-                        file.seek(SeekFrom::Start((pe_header + 4) as u64))?;
-                        let arch = file.read_u16::<LittleEndian>()?;
-
-                        if arch == 0x014c {
-                            os_type = Some(OsType::WindowsX32);
-                        } else if arch == 0x8664 {
-                            os_type = Some(OsType::WindowsX64);
+                    let os_types = match extension {
+                        "dll" => get_pe_os_types(path)?,
+                        "so" => get_elf_os_types(path)?,
+                        "dylib" | "jnilib" => get_mach_os_types(path)?,
+                        _ => {
+                            error!("Found excess file: {:?} in native dir!", path);
+                            continue;
                         }
-                    } else if extension.eq("so") {
-                        let mut file = File::open(path)?;
-                        file.seek(SeekFrom::Start(4))?;
-                        let arch = file.read_u8()?;
-                        if arch == 1 {
-                            os_type = Some(OsType::LinuxX32);
-                        } else if arch == 2 {
-                            os_type = Some(OsType::LinuxX64);
-                        }
-                    } else if extension.eq("dylib") || extension.eq("jnilib") {
-                        os_type = Some(OsType::MacOSX64);
-                    } else {
-                        error!("Found excess file: {:?} in native dir!", path);
+                    };
+                    if os_types.is_empty() {
+                        error!("Unknown file archetype: {:?}!", path);
                         continue;
                     }
-                    match os_type {
-                        Some(os_type) => {
-                            hashed_native.get_mut(&os_type).unwrap().insert(
-                                PathBuf::from(strip(path, "static/")?),
-                                create_remote_file(path, file_server.clone())?,
-                            );
-                        }
-                        None => error!("Unknown file archetype: {:?}!", path),
+                    let relative_path = RelativePath::from_path(path.strip_prefix("static/")?)?;
+                    let remote_file = create_remote_file(path, file_server.clone())?;
+                    for os_type in os_types {
+                        hashed_native
+                            .get_mut(&os_type)
+                            .unwrap()
+                            .insert(relative_path.clone(), remote_file.clone());
                     }
                 } else {
                     error!("Cannot get file: {:?} extension!", path);
@@ -292,9 +428,12 @@ impl SecurityManager {
         let jres = vec![
             (OsType::LinuxX64, "LinuxX64"),
             (OsType::LinuxX32, "LinuxX32"),
+            (OsType::LinuxArm64, "LinuxArm64"),
             (OsType::MacOSX64, "MacOSX64"),
+            (OsType::MacOSArm64, "MacOSArm64"),
             (OsType::WindowsX64, "WindowsX64"),
             (OsType::WindowsX32, "WindowsX32"),
+            (OsType::WindowsArm64, "WindowsArm64"),
         ];
 
         for jre in jres {
@@ -307,6 +446,70 @@ impl SecurityManager {
     }
 }
 
+const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+const CPU_TYPE_ARM64: u32 = 0x0100_000C;
+
+fn get_pe_os_types(path: &Path) -> Result<Vec<OsType>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(0x3C))?;
+    let pe_header = file.read_u32::<LittleEndian>()?;
+    file.seek(SeekFrom::Start((pe_header + 4) as u64))?;
+    let os_type = match file.read_u16::<LittleEndian>()? {
+        0x014C => Some(OsType::WindowsX32),
+        0x8664 => Some(OsType::WindowsX64),
+        0xAA64 => Some(OsType::WindowsArm64),
+        _ => None,
+    };
+    Ok(os_type.into_iter().collect())
+}
+
+fn get_elf_os_types(path: &Path) -> Result<Vec<OsType>> {
+    let mut header = [0u8; 20];
+    File::open(path)?.read_exact(&mut header)?;
+    if header[..4] != [0x7F, b'E', b'L', b'F'] {
+        return Ok(Vec::new());
+    }
+    let machine = match header[5] {
+        1 => LittleEndian::read_u16(&header[18..]),
+        2 => BigEndian::read_u16(&header[18..]),
+        _ => return Ok(Vec::new()),
+    };
+    let os_type = match (header[4], machine) {
+        (1, 0x03) => Some(OsType::LinuxX32),
+        (2, 0x3E) => Some(OsType::LinuxX64),
+        (2, 0xB7) => Some(OsType::LinuxArm64),
+        _ => None,
+    };
+    Ok(os_type.into_iter().collect())
+}
+
+fn get_mach_os_types(path: &Path) -> Result<Vec<OsType>> {
+    let mut file = File::open(path)?;
+    let cpu_types = match file.read_u32::<BigEndian>()? {
+        0xFEED_FACF => vec![file.read_u32::<BigEndian>()?],
+        0xCFFA_EDFE => vec![file.read_u32::<LittleEndian>()?],
+        magic @ 0xCAFE_BABE | magic @ 0xCAFE_BABF => {
+            let arch_count = file.read_u32::<BigEndian>()?;
+            let arch_size = if magic == 0xCAFE_BABE { 20 } else { 32 };
+            let mut cpu_types = Vec::new();
+            for arch in 0..arch_count as u64 {
+                file.seek(SeekFrom::Start(8 + arch * arch_size))?;
+                cpu_types.push(file.read_u32::<BigEndian>()?);
+            }
+            cpu_types
+        }
+        _ => Vec::new(),
+    };
+    Ok(cpu_types
+        .into_iter()
+        .filter_map(|cpu_type| match cpu_type {
+            CPU_TYPE_X86_64 => Some(OsType::MacOSX64),
+            CPU_TYPE_ARM64 => Some(OsType::MacOSArm64),
+            _ => None,
+        })
+        .collect())
+}
+
 fn strip_folder(path: &Path, save_number: usize, skip_number: usize) -> String {
     path.iter()
         .take(save_number)
@@ -317,23 +520,23 @@ fn strip_folder(path: &Path, save_number: usize, skip_number: usize) -> String {
 
 fn fill_map(
     iter: impl Iterator<Item = DirEntry>,
-    map: &mut HashMap<PathBuf, RemoteFile>,
+    map: &mut RemoteDirectory,
     file_server: String,
 ) -> Result<()> {
     for file in iter {
         let path = file.path();
-        let strip_path = if path.starts_with("static/jre") {
-            strip_folder(
+        let relative_path = if path.starts_with("static/jre") {
+            RelativePath::new(strip_folder(
                 path.strip_prefix("static/")
                     .expect("Failed to strip prefix!"),
                 1,
                 1,
-            )
+            ))?
         } else {
-            strip(path, "static/")?
+            RelativePath::from_path(path.strip_prefix("static/")?)?
         };
         map.insert(
-            PathBuf::from(strip_path),
+            relative_path,
             create_remote_file(path, file_server.clone())?,
         );
     }
@@ -376,3 +579,101 @@ fn strip(path: &Path, prefix: &str) -> Result<String> {
         })?
         .to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("nsl-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn elf(class: u8, endian: u8, machine: u16) -> Vec<u8> {
+        let mut header = vec![0x7F, b'E', b'L', b'F', class, endian];
+        header.resize(18, 0);
+        if endian == 1 {
+            header.extend_from_slice(&machine.to_le_bytes());
+        } else {
+            header.extend_from_slice(&machine.to_be_bytes());
+        }
+        header
+    }
+
+    #[test]
+    fn elf_machine() {
+        let cases = [
+            ("elf-x64", elf(2, 1, 0x3E), vec![OsType::LinuxX64]),
+            ("elf-x32", elf(1, 1, 0x03), vec![OsType::LinuxX32]),
+            ("elf-arm64", elf(2, 1, 0xB7), vec![OsType::LinuxArm64]),
+            ("elf-armv7", elf(1, 1, 0x28), vec![]),
+            ("elf-be", elf(2, 2, 0x3E), vec![OsType::LinuxX64]),
+        ];
+        for (name, bytes, expected) in cases.iter() {
+            let path = write_temp(name, bytes);
+            assert_eq!(&get_elf_os_types(&path).unwrap(), expected, "{}", name);
+            std::fs::remove_file(path).unwrap();
+        }
+        let path = write_temp("elf-bad", b"MZ not an elf file..");
+        assert!(get_elf_os_types(&path).unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn pe_machine() {
+        for (name, machine, expected) in [
+            ("pe-x64", 0x8664u16, Some(OsType::WindowsX64)),
+            ("pe-x32", 0x014C, Some(OsType::WindowsX32)),
+            ("pe-arm64", 0xAA64, Some(OsType::WindowsArm64)),
+            ("pe-unknown", 0x01C4, None),
+        ]
+        .iter()
+        {
+            let mut bytes = vec![0u8; 0x80];
+            bytes[..2].copy_from_slice(b"MZ");
+            bytes[0x3C..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+            bytes[0x40..0x44].copy_from_slice(b"PE\0\0");
+            bytes[0x44..0x46].copy_from_slice(&machine.to_le_bytes());
+            let path = write_temp(name, &bytes);
+            let os_types = get_pe_os_types(&path).unwrap();
+            assert_eq!(os_types.first(), expected.as_ref(), "{}", name);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn mach_thin() {
+        let mut bytes = 0xFEED_FACFu32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&CPU_TYPE_ARM64.to_le_bytes());
+        let path = write_temp("mach-thin", &bytes);
+        assert_eq!(get_mach_os_types(&path).unwrap(), vec![OsType::MacOSArm64]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn mach_universal() {
+        for (name, magic, arch_size) in [
+            ("mach-fat", 0xCAFE_BABEu32, 20),
+            ("mach-fat64", 0xCAFE_BABF, 32),
+        ]
+        .iter()
+        {
+            let mut bytes = magic.to_be_bytes().to_vec();
+            bytes.extend_from_slice(&2u32.to_be_bytes());
+            for cpu_type in [CPU_TYPE_X86_64, CPU_TYPE_ARM64].iter() {
+                let mut arch = vec![0u8; *arch_size];
+                arch[..4].copy_from_slice(&cpu_type.to_be_bytes());
+                bytes.extend_from_slice(&arch);
+            }
+            let path = write_temp(name, &bytes);
+            assert_eq!(
+                get_mach_os_types(&path).unwrap(),
+                vec![OsType::MacOSX64, OsType::MacOSArm64],
+                "{}",
+                name
+            );
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
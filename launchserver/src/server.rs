@@ -1,39 +1,126 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use warp::Filter;
 
-use crate::server::auth::{has_join, HasJoinRequest};
+use crate::server::auth::{
+    get_profile, get_public_keys, get_uuid, get_uuids, has_join, join, HasJoinRequest, ProfileQuery,
+};
 use crate::server::websocket::ws_api;
 use crate::LaunchServer;
 
 mod auth;
+mod authserver;
+pub mod limiter;
 pub mod profile;
+mod proxy;
 mod websocket;
 
 pub async fn start(data: Arc<RwLock<LaunchServer>>) {
     let config = data.clone().read().await.config.clone();
     let data = warp::any().map(move || data.clone());
     let dir = warp::path("files").and(warp::fs::dir("static"));
-    let client_ip = warp::header("x-real-ip")
-        .or(warp::header("x-forwarded-for"))
-        .unify()
-        .or(warp::addr::remote().map(|addr: Option<SocketAddr>| addr.expect("Ip not found")))
-        .unify();
+    // Only texture files, index.json stays private
+    let textures = warp::path("textures")
+        .and(warp::get())
+        .and(warp::path::peek())
+        .and_then(|path: warp::path::Peek| async move {
+            if path.as_str().ends_with(".png") {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+        .and(warp::fs::dir(config.texture.storage.directory.clone()));
+    let trusted_proxies = config.trusted_proxies.clone();
+    let client_ip = warp::addr::remote()
+        .and(warp::header::optional::<String>("x-real-ip"))
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .map(
+            move |addr: Option<SocketAddr>,
+                  real_ip: Option<String>,
+                  forwarded_for: Option<String>| {
+                proxy::get_client_ip(
+                    &trusted_proxies,
+                    addr.expect("Ip not found").ip(),
+                    real_ip.as_deref(),
+                    forwarded_for.as_deref(),
+                )
+            },
+        );
     let ws = warp::path("api")
         .and(warp::ws())
         .and(data.clone())
-        .and(client_ip)
-        .map(|ws: warp::ws::Ws, launcher, addr: SocketAddr| {
-            ws.on_upgrade(move |socket| ws_api(socket, launcher, format!("{:?}", addr.ip())))
+        .and(client_ip.clone())
+        .map(|ws: warp::ws::Ws, launcher, ip: IpAddr| {
+            ws.on_upgrade(move |socket| ws_api(socket, launcher, ip.to_string()))
         });
+    let join_server = warp::path!("session" / "minecraft" / "join")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(data.clone())
+        .and(client_ip.clone())
+        .and_then(join);
     let has_joined = warp::path("hasJoined")
         .and(warp::get())
         .and(warp::query::<HasJoinRequest>())
         .and(data.clone())
         .and_then(has_join);
-    let routes = dir.or(ws).or(has_joined);
+    let profile = warp::path!("session" / "minecraft" / "profile" / String)
+        .and(warp::get())
+        .and(warp::query::<ProfileQuery>())
+        .and(data.clone())
+        .and_then(get_profile);
+    let uuid = warp::path!("users" / "profiles" / "minecraft" / String)
+        .and(warp::get())
+        .and(data.clone())
+        .and_then(get_uuid);
+    let uuids = warp::path!("profiles" / "minecraft")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(data.clone())
+        .and_then(get_uuids);
+    let public_keys = warp::path!("publickeys")
+        .and(warp::get())
+        .and(data.clone())
+        .and_then(get_public_keys);
+    let authserver = warp::path("authserver").and(warp::post()).and(
+        warp::path!("authenticate")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and(client_ip.clone())
+            .and_then(authserver::authenticate)
+            .or(warp::path!("refresh")
+                .and(warp::body::json())
+                .and(data.clone())
+                .and(client_ip.clone())
+                .and_then(authserver::refresh))
+            .or(warp::path!("validate")
+                .and(warp::body::json())
+                .and(data.clone())
+                .and_then(authserver::validate))
+            .or(warp::path!("invalidate")
+                .and(warp::body::json())
+                .and(data.clone())
+                .and_then(authserver::invalidate))
+            .or(warp::path!("signout")
+                .and(warp::body::json())
+                .and(data.clone())
+                .and(client_ip)
+                .and_then(authserver::signout)),
+    );
+    let routes = dir
+        .or(textures)
+        .or(ws)
+        .or(join_server)
+        .or(has_joined)
+        .or(profile)
+        .or(uuid)
+        .or(uuids)
+        .or(public_keys)
+        .or(authserver);
     warp::serve(routes)
         .run(SocketAddr::from_str(&config.bind_address).expect("Can't parse server address"))
         .await;
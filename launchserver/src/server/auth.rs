@@ -1,11 +1,15 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::config::auth::{is_not_found, Entry};
 use crate::LaunchServer;
+use log::{debug, error};
 use serde_json::Value;
+use std::net::IpAddr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use warp::http::StatusCode;
+use warp::reply::Response;
 use warp::Reply;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,43 +25,237 @@ pub struct JoinRequest {
 pub struct HasJoinRequest {
     username: String,
     server_id: String,
+    // Sent by game server when prevent-proxy-connections is enabled
+    #[serde(default)]
+    ip: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileQuery {
+    #[serde(default = "default_unsigned")]
+    unsigned: bool,
+}
+
+fn default_unsigned() -> bool {
+    true
+}
+
+// Mojang allows only 10 names in one lookup
+const MAX_NAMES: usize = 10;
+
+fn create_profile(data: &LaunchServer, entry: &Entry, signed: bool) -> Value {
+    let textures = data
+        .config
+        .texture
+        .get_textures_property(entry, &data.textures);
+    let mut property = serde_json::json!({
+        "name": "textures",
+        "value": base64::encode(&textures.to_string())
+    });
+    if signed {
+        // Signed value keeps the timestamp of first signing
+        match data.security.sign_textures(&entry.uuid, &textures) {
+            Ok((value, signature)) => {
+                property["value"] = Value::String(value);
+                property["signature"] = Value::String(signature);
+            }
+            Err(e) => error!("Can't sign textures of {}: {}", entry.username, e),
+        }
+    }
+    serde_json::json!({
+        "id": entry.uuid.to_simple().encode_lower(&mut Uuid::encode_buffer()),
+        "name": entry.username,
+        "properties": [property]
+    })
+}
+
+pub(crate) fn no_content() -> Response {
+    warp::reply::with_status(warp::reply(), StatusCode::NO_CONTENT).into_response()
+}
+
+pub(crate) fn error(status: StatusCode, error: &str, message: &str) -> Response {
+    warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({
+            "error": error,
+            "errorMessage": message
+        })),
+        status,
+    )
+    .into_response()
+}
+
+pub(crate) fn unavailable() -> Response {
+    error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "ServiceUnavailableException",
+        "Auth provider is unavailable, try again later.",
+    )
+}
+
+fn provider_error(error: anyhow::Error) -> Response {
+    error!("Auth provider error: {}", error);
+    unavailable()
+}
+
+// Yggdrasil join used by vanilla clients, launcher clients join via websocket
+pub(crate) async fn join(
+    request: JoinRequest,
+    data: Arc<RwLock<LaunchServer>>,
+    ip: IpAddr,
+) -> Result<Response, warp::Rejection> {
+    let data = data.read().await;
+    match data.sessions.get(&request.access_token) {
+        Some(session) if session.uuid == request.selected_profile => {
+            if let Err(e) = data
+                .config
+                .auth
+                .update_server_id(&request.selected_profile, &request.server_id)
+                .await
+            {
+                return Ok(provider_error(e));
+            }
+            data.sessions.join(
+                request.selected_profile,
+                &request.server_id,
+                &ip.to_string(),
+            );
+            Ok(no_content())
+        }
+        _ => Ok(error(
+            StatusCode::FORBIDDEN,
+            "ForbiddenOperationException",
+            "Invalid token.",
+        )),
+    }
 }
 
 pub(crate) async fn has_join(
     request: HasJoinRequest,
     data: Arc<RwLock<LaunchServer>>,
-) -> Result<impl Reply, warp::Rejection> {
+) -> Result<Response, warp::Rejection> {
     let data = data.read().await;
-    let texture = &data.config.texture;
-    let auth = &data.config.auth;
-    let entry = auth.get_entry_from_name(&request.username).await;
-    match entry {
-        Err(_e) => Ok(warp::reply::with_status(
-            warp::reply::json(&Value::default()),
-            StatusCode::BAD_REQUEST,
+    let entry = match data
+        .config
+        .auth
+        .get_entry_from_name(&request.username)
+        .await
+    {
+        Ok(entry) => entry,
+        Err(e) if is_not_found(&e) => return Ok(no_content()),
+        Err(e) => return Ok(provider_error(e)),
+    };
+    if data
+        .sessions
+        .check_join(&entry.uuid, &request.server_id, request.ip.as_deref())
+    {
+        Ok(warp::reply::json(&create_profile(&data, &entry, true)).into_response())
+    } else {
+        Ok(no_content())
+    }
+}
+
+pub(crate) async fn get_profile(
+    uuid: String,
+    query: ProfileQuery,
+    data: Arc<RwLock<LaunchServer>>,
+) -> Result<Response, warp::Rejection> {
+    let uuid = match Uuid::parse_str(&uuid) {
+        Ok(uuid) => uuid,
+        Err(_) => {
+            return Ok(error(
+                StatusCode::BAD_REQUEST,
+                "IllegalArgumentException",
+                "Invalid UUID string",
+            ))
+        }
+    };
+    let data = data.read().await;
+    match data.config.auth.get_entry(&uuid).await {
+        Ok(entry) => {
+            Ok(warp::reply::json(&create_profile(&data, &entry, !query.unsigned)).into_response())
+        }
+        Err(e) if is_not_found(&e) => {
+            debug!("Profile {} not found", uuid);
+            Ok(no_content())
+        }
+        Err(e) => Ok(provider_error(e)),
+    }
+}
+
+pub(crate) async fn get_uuid(
+    username: String,
+    data: Arc<RwLock<LaunchServer>>,
+) -> Result<Response, warp::Rejection> {
+    let data = data.read().await;
+    match data.config.auth.get_entry_from_name(&username).await {
+        Ok(entry) => Ok(warp::reply::json(&create_name_entry(&entry)).into_response()),
+        Err(e) if is_not_found(&e) => Ok(error(
+            StatusCode::NOT_FOUND,
+            "NotFoundException",
+            &format!("Couldn't find any profile with name {}", username),
         )),
-        Ok(e) => {
-            if e.server_id.is_some() && e.server_id.clone().unwrap().eq(&request.server_id) {
-                let texture = base64::encode(&texture.get_textures_property(&e).to_string());
-                Ok(warp::reply::with_status(
-                    warp::reply::json(&serde_json::json!({
-                        "id":  e.uuid.to_simple().encode_lower(&mut Uuid::encode_buffer()),
-                        "name": request.username,
-                        "properties": [
-                            {
-                                "name": "textures",
-                                "value": texture
-                            }
-                        ]
-                    })),
-                    StatusCode::OK,
-                ))
-            } else {
-                Ok(warp::reply::with_status(
-                    warp::reply::json(&Value::default()),
-                    StatusCode::BAD_REQUEST,
-                ))
-            }
+        Err(e) => Ok(provider_error(e)),
+    }
+}
+
+pub(crate) async fn get_uuids(
+    usernames: Vec<String>,
+    data: Arc<RwLock<LaunchServer>>,
+) -> Result<Response, warp::Rejection> {
+    if usernames.len() > MAX_NAMES {
+        return Ok(error(
+            StatusCode::BAD_REQUEST,
+            "IllegalArgumentException",
+            &format!(
+                "Not more than {} profile names per call is allowed",
+                MAX_NAMES
+            ),
+        ));
+    }
+    let data = data.read().await;
+    let mut entries: Vec<Entry> = Vec::new();
+    for username in usernames {
+        if entries
+            .iter()
+            .any(|entry| entry.username.eq_ignore_ascii_case(&username))
+        {
+            continue;
+        }
+        // Unknown names are just missing in response
+        match data.config.auth.get_entry_from_name(&username).await {
+            Ok(entry) => entries.push(entry),
+            Err(e) if is_not_found(&e) => {}
+            Err(e) => return Ok(provider_error(e)),
+        }
+    }
+    let response: Vec<Value> = entries.iter().map(create_name_entry).collect();
+    Ok(warp::reply::json(&response).into_response())
+}
+
+// Public key for profileProperty signatures, authlib-injector reads it from this endpoint
+pub(crate) async fn get_public_keys(
+    data: Arc<RwLock<LaunchServer>>,
+) -> Result<Response, warp::Rejection> {
+    let data = data.read().await;
+    match data.security.get_texture_public_key() {
+        Ok(key) => Ok(warp::reply::json(&serde_json::json!({
+            "profilePropertyKeys": [{ "publicKey": base64::encode(&key) }],
+            "playerCertificateKeys": []
+        }))
+        .into_response()),
+        Err(e) => {
+            error!("Can't encode texture public key: {}", e);
+            Ok(
+                warp::reply::with_status(warp::reply(), StatusCode::INTERNAL_SERVER_ERROR)
+                    .into_response(),
+            )
         }
     }
 }
+
+fn create_name_entry(entry: &Entry) -> Value {
+    serde_json::json!({
+        "id": entry.uuid.to_simple().encode_lower(&mut Uuid::encode_buffer()),
+        "name": entry.username
+    })
+}
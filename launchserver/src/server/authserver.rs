@@ -0,0 +1,235 @@
+use log::error;
+use serde::Deserialize;
+use serde_json::Value;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::Reply;
+
+use crate::server::auth::{error, no_content, unavailable};
+use crate::session;
+use crate::session::Session;
+use crate::LaunchServer;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticateRequest {
+    username: String,
+    password: String,
+    #[serde(default)]
+    client_token: Option<String>,
+    #[serde(default)]
+    request_user: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshRequest {
+    access_token: String,
+    #[serde(default)]
+    client_token: Option<String>,
+    #[serde(default)]
+    request_user: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenRequest {
+    access_token: String,
+    #[serde(default)]
+    client_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SignoutRequest {
+    username: String,
+    password: String,
+}
+
+fn forbidden(message: &str) -> Response {
+    error(
+        StatusCode::FORBIDDEN,
+        "ForbiddenOperationException",
+        message,
+    )
+}
+
+fn get_profile(session: &Session) -> Value {
+    serde_json::json!({
+        "id": session.uuid.to_simple().encode_lower(&mut Uuid::encode_buffer()),
+        "name": session.username
+    })
+}
+
+fn create_response(access_token: String, session: &Session, request_user: bool) -> Response {
+    let mut response = serde_json::json!({
+        "accessToken": access_token,
+        "clientToken": session.client_token,
+        "availableProfiles": [get_profile(session)],
+        "selectedProfile": get_profile(session)
+    });
+    if request_user {
+        response["user"] = serde_json::json!({
+            "id": session.uuid.to_simple().encode_lower(&mut Uuid::encode_buffer()),
+            "username": session.username,
+            "properties": []
+        });
+    }
+    warp::reply::json(&response).into_response()
+}
+
+// Same limiter as launcher auth, otherwise this endpoint would bypass it
+async fn check_credentials(
+    data: &LaunchServer,
+    username: &str,
+    password: &str,
+    ip: &str,
+) -> Result<Uuid, Response> {
+    let attempt = data.auth_limiter.try_acquire(ip, username).map_err(|_| {
+        error(
+            StatusCode::TOO_MANY_REQUESTS,
+            "ForbiddenOperationException",
+            "Invalid credentials. Too many login attempts, try again later.",
+        )
+    })?;
+    let result = data
+        .config
+        .auth
+        .auth(username, password, ip)
+        .await
+        .map_err(|e| {
+            error!("Auth provider error: {}", e);
+            unavailable()
+        })?;
+    match result.uuid {
+        Some(uuid) if result.message.is_none() => {
+            attempt.success();
+            Ok(uuid)
+        }
+        _ => {
+            attempt.fail();
+            Err(forbidden(
+                "Invalid credentials. Invalid username or password.",
+            ))
+        }
+    }
+}
+
+async fn start_session(
+    data: &LaunchServer,
+    (access_token, session): (String, Session),
+    request_user: bool,
+) -> Response {
+    match data
+        .config
+        .auth
+        .update_access_token(&session.uuid, &access_token)
+        .await
+    {
+        Ok(()) => create_response(access_token, &session, request_user),
+        Err(e) => {
+            error!("Auth provider error: {}", e);
+            data.sessions.revoke(&access_token);
+            unavailable()
+        }
+    }
+}
+
+pub(crate) async fn authenticate(
+    request: AuthenticateRequest,
+    data: Arc<RwLock<LaunchServer>>,
+    ip: IpAddr,
+) -> Result<Response, warp::Rejection> {
+    let data = data.read().await;
+    let ip = ip.to_string();
+    let uuid = match check_credentials(&data, &request.username, &request.password, &ip).await {
+        Ok(uuid) => uuid,
+        Err(response) => return Ok(response),
+    };
+    let username = match data.config.auth.get_entry(&uuid).await {
+        Ok(entry) => entry.username,
+        Err(_) => request.username.clone(),
+    };
+    let client_token = request
+        .client_token
+        .clone()
+        .unwrap_or_else(|| Uuid::new_v4().to_simple().to_string());
+    let session = data
+        .sessions
+        .create(uuid, &username, &ip, None, true, Some(client_token));
+    Ok(start_session(&data, session, request.request_user).await)
+}
+
+pub(crate) async fn refresh(
+    request: RefreshRequest,
+    data: Arc<RwLock<LaunchServer>>,
+    ip: IpAddr,
+) -> Result<Response, warp::Rejection> {
+    let data = data.read().await;
+    let session = data.sessions.renew(
+        &request.access_token,
+        request.client_token.as_deref(),
+        &ip.to_string(),
+    );
+    match session {
+        Some(session) => Ok(start_session(&data, session, request.request_user).await),
+        None => Ok(forbidden("Invalid token.")),
+    }
+}
+
+pub(crate) async fn validate(
+    request: TokenRequest,
+    data: Arc<RwLock<LaunchServer>>,
+) -> Result<Response, warp::Rejection> {
+    let data = data.read().await;
+    match data.sessions.get(&request.access_token) {
+        Some(session) if session.matches_client(request.client_token.as_deref()) => {
+            Ok(no_content())
+        }
+        _ => Ok(forbidden("Invalid token.")),
+    }
+}
+
+pub(crate) async fn invalidate(
+    request: TokenRequest,
+    data: Arc<RwLock<LaunchServer>>,
+) -> Result<Response, warp::Rejection> {
+    let data = data.read().await;
+    match data.sessions.get(&request.access_token) {
+        Some(session) if session.matches_client(request.client_token.as_deref()) => {
+            match session::revoke(&data, &request.access_token).await {
+                Ok(_) => Ok(no_content()),
+                Err(e) => {
+                    error!("Auth provider error: {}", e);
+                    Ok(unavailable())
+                }
+            }
+        }
+        _ => Ok(no_content()),
+    }
+}
+
+pub(crate) async fn signout(
+    request: SignoutRequest,
+    data: Arc<RwLock<LaunchServer>>,
+    ip: IpAddr,
+) -> Result<Response, warp::Rejection> {
+    let data = data.read().await;
+    let ip = ip.to_string();
+    match check_credentials(&data, &request.username, &request.password, &ip).await {
+        Ok(uuid) => {
+            data.sessions.revoke_user(&uuid);
+            match session::rotate_provider_token(&data.config.auth, &uuid).await {
+                Ok(()) => Ok(no_content()),
+                Err(e) => {
+                    error!("Auth provider error: {}", e);
+                    Ok(unavailable())
+                }
+            }
+        }
+        Err(response) => Ok(response),
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::{AuthLimiterConfig, LimitRule};
+
+#[derive(Default)]
+struct Attempts {
+    failures: Vec<Instant>,
+    lockouts: u32,
+    locked_until: Option<Instant>,
+    last_failure: Option<Instant>,
+    // Attempts being checked by provider, counted as failures until they finish
+    in_flight: u32,
+}
+
+struct Limiter {
+    rule: LimitRule,
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+impl Limiter {
+    fn new(rule: LimitRule) -> Self {
+        Limiter {
+            rule,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Attempt is counted before credentials are checked, so concurrent requests can't
+    // exceed max attempts. It must be finished by fail, reset or release
+    fn try_acquire(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let window = Duration::from_secs(self.rule.window);
+        let forget = Duration::from_secs(self.rule.max_lockout.max(self.rule.window));
+        let mut attempts = self.attempts.lock().expect("Limiter is poisoned");
+        // Lockout counter is kept until key stays quiet long enough, so backoff keeps growing
+        attempts.retain(|_, attempts| {
+            let quiet = attempts
+                .last_failure
+                .map(|last| now.saturating_duration_since(last))
+                .unwrap_or(forget);
+            attempts.in_flight > 0
+                || matches!(attempts.locked_until, Some(until) if until > now)
+                || quiet < forget
+        });
+        let entry = attempts.entry(key.to_string()).or_default();
+        if let Some(until) = entry.locked_until.filter(|until| *until > now) {
+            return Err(until - now);
+        }
+        entry
+            .failures
+            .retain(|failure| now.saturating_duration_since(*failure) < window);
+        if entry.failures.len() + entry.in_flight as usize >= self.rule.max_attempts as usize {
+            return Err(Duration::from_secs(0));
+        }
+        entry.in_flight += 1;
+        Ok(())
+    }
+
+    fn release(&self, key: &str) {
+        let mut attempts = self.attempts.lock().expect("Limiter is poisoned");
+        if let Some(entry) = attempts.get_mut(key) {
+            entry.in_flight = entry.in_flight.saturating_sub(1);
+        }
+    }
+
+    fn fail(&self, key: &str, now: Instant) {
+        let window = Duration::from_secs(self.rule.window);
+        let mut attempts = self.attempts.lock().expect("Limiter is poisoned");
+        let entry = attempts.entry(key.to_string()).or_default();
+        entry.in_flight = entry.in_flight.saturating_sub(1);
+        entry.last_failure = Some(now);
+        entry
+            .failures
+            .retain(|failure| now.saturating_duration_since(*failure) < window);
+        entry.failures.push(now);
+        if entry.failures.len() >= self.rule.max_attempts as usize {
+            entry.failures.clear();
+            entry.locked_until = Some(now + self.get_lockout_duration(entry.lockouts));
+            entry.lockouts += 1;
+        }
+    }
+
+    // Other attempts in flight keep their slots
+    fn reset(&self, key: &str) {
+        let mut attempts = self.attempts.lock().expect("Limiter is poisoned");
+        if let Some(entry) = attempts.get_mut(key) {
+            let in_flight = entry.in_flight.saturating_sub(1);
+            if in_flight == 0 {
+                attempts.remove(key);
+            } else {
+                *entry = Attempts {
+                    in_flight,
+                    ..Attempts::default()
+                };
+            }
+        }
+    }
+
+    fn get_lockout_duration(&self, lockouts: u32) -> Duration {
+        let seconds = self.rule.lockout as f64 * self.rule.backoff.powi(lockouts as i32);
+        Duration::from_secs_f64(seconds.min(self.rule.max_lockout as f64).max(0.0))
+    }
+}
+
+pub struct AuthLimiter {
+    enabled: bool,
+    ip: Limiter,
+    login: Limiter,
+}
+
+impl AuthLimiter {
+    pub fn new(config: &AuthLimiterConfig) -> Self {
+        AuthLimiter {
+            enabled: config.enabled,
+            ip: Limiter::new(config.ip.clone()),
+            login: Limiter::new(config.login.clone()),
+        }
+    }
+
+    // Returns remaining lockout if attempt isn't allowed
+    pub fn try_acquire(&self, ip: &str, login: &str) -> Result<AuthAttempt<'_>, Duration> {
+        let login = login.to_lowercase();
+        if self.enabled {
+            let now = Instant::now();
+            self.ip.try_acquire(ip, now)?;
+            if let Err(lockout) = self.login.try_acquire(&login, now) {
+                self.ip.release(ip);
+                return Err(lockout);
+            }
+        }
+        Ok(AuthAttempt {
+            limiter: self,
+            ip: ip.to_string(),
+            login,
+            finished: !self.enabled,
+        })
+    }
+}
+
+// Dropping unfinished attempt (e.g. on provider error) releases it without counting a failure
+pub struct AuthAttempt<'a> {
+    limiter: &'a AuthLimiter,
+    ip: String,
+    login: String,
+    finished: bool,
+}
+
+impl AuthAttempt<'_> {
+    pub fn fail(mut self) {
+        let now = Instant::now();
+        self.limiter.ip.fail(&self.ip, now);
+        self.limiter.login.fail(&self.login, now);
+        self.finished = true;
+    }
+
+    // Ip counter isn't reset, otherwise one valid account would unlock brute force from that ip
+    pub fn success(mut self) {
+        self.limiter.ip.release(&self.ip);
+        self.limiter.login.reset(&self.login);
+        self.finished = true;
+    }
+}
+
+impl Drop for AuthAttempt<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.limiter.ip.release(&self.ip);
+            self.limiter.login.release(&self.login);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+
+    fn rule() -> LimitRule {
+        LimitRule {
+            max_attempts: 3,
+            window: 60,
+            lockout: 10,
+            backoff: 2.0,
+            max_lockout: 35,
+        }
+    }
+
+    fn fail_times(limiter: &Limiter, times: usize, now: Instant) {
+        for _ in 0..times {
+            limiter.try_acquire("key", now).unwrap();
+            limiter.fail("key", now);
+        }
+    }
+
+    fn get_lockout(limiter: &Limiter, key: &str, now: Instant) -> Option<Duration> {
+        let lockout = limiter.try_acquire(key, now).err();
+        if lockout.is_none() {
+            limiter.release(key);
+        }
+        lockout
+    }
+
+    #[test]
+    fn locks_after_max_attempts() {
+        let limiter = Limiter::new(rule());
+        let now = Instant::now();
+        fail_times(&limiter, 2, now);
+        assert_eq!(get_lockout(&limiter, "key", now), None);
+        fail_times(&limiter, 1, now);
+        assert_eq!(
+            get_lockout(&limiter, "key", now),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(get_lockout(&limiter, "other", now), None);
+        assert_eq!(
+            get_lockout(&limiter, "key", now + Duration::from_secs(10)),
+            None
+        );
+    }
+
+    #[test]
+    fn failures_outside_window_are_forgotten() {
+        let limiter = Limiter::new(rule());
+        let now = Instant::now();
+        fail_times(&limiter, 2, now);
+        let later = now + Duration::from_secs(61);
+        fail_times(&limiter, 1, later);
+        assert_eq!(get_lockout(&limiter, "key", later), None);
+    }
+
+    #[test]
+    fn lockout_backoff_is_capped() {
+        let limiter = Limiter::new(rule());
+        let mut now = Instant::now();
+        for expected in [10, 20, 35, 35].iter() {
+            fail_times(&limiter, 3, now);
+            let lockout = get_lockout(&limiter, "key", now).unwrap();
+            assert_eq!(lockout, Duration::from_secs(*expected));
+            now += lockout;
+        }
+    }
+
+    #[test]
+    fn backoff_resets_after_quiet_period() {
+        let limiter = Limiter::new(rule());
+        let now = Instant::now();
+        fail_times(&limiter, 3, now);
+        let later = now + Duration::from_secs(10 + 60);
+        fail_times(&limiter, 3, later);
+        assert_eq!(
+            get_lockout(&limiter, "key", later),
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn in_flight_attempts_count_towards_limit() {
+        let limiter = Limiter::new(rule());
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.try_acquire("key", now).unwrap();
+        }
+        assert!(limiter.try_acquire("key", now).is_err());
+        // Released attempt (e.g. provider error) frees its slot without counting a failure
+        limiter.release("key");
+        limiter.try_acquire("key", now).unwrap();
+        for _ in 0..3 {
+            limiter.fail("key", now);
+        }
+        assert_eq!(
+            get_lockout(&limiter, "key", now),
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    fn auth_limiter() -> AuthLimiter {
+        AuthLimiter::new(&AuthLimiterConfig {
+            enabled: true,
+            ip: rule(),
+            login: rule(),
+        })
+    }
+
+    #[test]
+    fn success_resets_only_login() {
+        let limiter = auth_limiter();
+        for _ in 0..2 {
+            limiter.try_acquire("127.0.0.1", "Player").unwrap().fail();
+        }
+        limiter
+            .try_acquire("127.0.0.1", "player")
+            .unwrap()
+            .success();
+        limiter.try_acquire("127.0.0.1", "PLAYER").unwrap().fail();
+        // Ip counter reached max attempts, login counter was reset
+        assert!(limiter.try_acquire("127.0.0.1", "other").is_err());
+        assert!(limiter.try_acquire("127.0.0.2", "player").is_ok());
+    }
+
+    #[test]
+    fn concurrent_attempts_are_limited() {
+        let limiter = auth_limiter();
+        let barrier = Barrier::new(10);
+        let attempts = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..10)
+                .map(|i| {
+                    let (limiter, barrier) = (&limiter, &barrier);
+                    scope.spawn(move || {
+                        let ip = format!("127.0.0.{}", i);
+                        barrier.wait();
+                        let attempt = limiter.try_acquire(&ip, "player");
+                        // Every thread tries before any attempt finishes
+                        barrier.wait();
+                        attempt.map(AuthAttempt::fail).is_ok()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .filter(|acquired| *acquired)
+                .count()
+        });
+        assert_eq!(attempts, 3);
+        assert!(limiter.try_acquire("127.0.0.100", "player").is_err());
+    }
+}
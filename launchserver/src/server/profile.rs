@@ -1,22 +1,33 @@
-use launcher_api::profile::{Profile, ProfileInfo};
+use anyhow::{Context, Result};
+use launcher_api::profile::{
+    Argument, ArgumentValue, Library, Profile, ProfileInfo, Rule, PROFILE_SCHEMA_VERSION,
+};
+use log::{error, warn};
+use serde_json::Value;
 use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+mod inheritance;
+mod migration;
+
 pub fn get_profiles() -> (HashMap<String, Profile>, Vec<ProfileInfo>) {
-    let profiles: HashMap<String, Profile> = WalkDir::new("static/profiles")
-        .min_depth(2)
-        .max_depth(3)
-        .into_iter()
-        .flat_map(|v| v.ok())
-        .filter(|e| {
-            e.metadata().map(|m| m.is_file()).unwrap_or(false) && e.file_name().eq("profile.json")
-        })
-        .flat_map(|e| File::open(e.into_path()).ok())
-        .flat_map(|f| serde_json::from_reader::<File, Profile>(f).ok())
-        .map(|profile| (profile.name.clone(), profile))
+    let raw_profiles = read_raw_profiles();
+    let values: HashMap<String, Value> = raw_profiles
+        .iter()
+        .map(|(name, (_, profile))| (name.clone(), profile.clone()))
         .collect();
+    let mut profiles: HashMap<String, Profile> = HashMap::new();
+    for (name, (path, profile)) in raw_profiles {
+        match load_profile(&name, profile, &values) {
+            Ok(profile) => {
+                profiles.insert(name, profile);
+            }
+            Err(e) => error!("Can't load profile {:?}: {:#}", path, e),
+        }
+    }
     let profiles_info = profiles
         .values()
         .map(|profile| {
@@ -37,3 +48,95 @@ pub fn get_profiles() -> (HashMap<String, Profile>, Vec<ProfileInfo>) {
         .collect();
     (profiles, profiles_info)
 }
+
+fn read_raw_profiles() -> HashMap<String, (PathBuf, Value)> {
+    let mut profiles = HashMap::new();
+    for entry in WalkDir::new("static/profiles")
+        .min_depth(2)
+        .max_depth(3)
+        .into_iter()
+        .filter_entry(|e| e.path() != Path::new(inheritance::TEMPLATES_DIR))
+        .flat_map(|v| v.ok())
+        .filter(|e| {
+            e.metadata().map(|m| m.is_file()).unwrap_or(false) && e.file_name().eq("profile.json")
+        })
+    {
+        let path = entry.into_path();
+        let profile = match read_raw_profile(&path) {
+            Ok(profile) => profile,
+            Err(e) => {
+                error!("Can't load profile {:?}: {:#}", path, e);
+                continue;
+            }
+        };
+        let name = match profile.get("name").and_then(Value::as_str) {
+            Some(name) => name.to_string(),
+            None => {
+                error!("Can't load profile {:?}: missing field `name`", path);
+                continue;
+            }
+        };
+        if profiles.contains_key(&name) {
+            error!(
+                "Profile {:?} use name '{}' that already exists, skip it!",
+                path, name
+            );
+            continue;
+        }
+        profiles.insert(name, (path, profile));
+    }
+    profiles
+}
+
+fn read_raw_profile(path: &Path) -> Result<Value> {
+    serde_json::from_reader(File::open(path)?).context("Profile is not valid JSON")
+}
+
+fn load_profile(name: &str, profile: Value, profiles: &HashMap<String, Value>) -> Result<Profile> {
+    let profile = inheritance::resolve(name, profile, profiles)?;
+    let version = migration::get_schema_version(&profile)?;
+    let profile = migration::migrate(profile)?;
+    if version < PROFILE_SCHEMA_VERSION {
+        warn!(
+            "Profile '{}' was migrated from schema version {} to {}, please update it!",
+            name, version, PROFILE_SCHEMA_VERSION
+        );
+    }
+    let profile: Profile = serde_json::from_value(profile)?;
+    check_rules(&profile)?;
+    Ok(profile)
+}
+
+// os.version regexes are not evaluated, so profile would behave differently than intended
+fn check_rules(profile: &Profile) -> Result<()> {
+    let library_rules = profile.libraries.iter().map(|library| match library {
+        Library::Conditional { path, rules } => (path.as_str(), rules.as_slice()),
+        Library::Path(path) => (path.as_str(), &[][..]),
+    });
+    let argument_rules = profile
+        .jvm_args
+        .iter()
+        .chain(profile.client_args.iter())
+        .filter_map(|argument| match argument {
+            Argument::Conditional { value, rules } => {
+                Some((get_first_value(value), rules.as_slice()))
+            }
+            Argument::Value(_) => None,
+        });
+    for (item, rules) in library_rules.chain(argument_rules) {
+        if rules.iter().any(Rule::has_os_version) {
+            return Err(anyhow::anyhow!(
+                "Rules with os.version are not supported, used by '{}'",
+                item
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn get_first_value(value: &ArgumentValue) -> &str {
+    match value {
+        ArgumentValue::Single(value) => value,
+        ArgumentValue::Multiple(values) => values.first().map(String::as_str).unwrap_or_default(),
+    }
+}
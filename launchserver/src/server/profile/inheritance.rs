@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use launcher_api::validation::RelativePath;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+pub const TEMPLATES_DIR: &str = "static/profiles/_templates";
+
+// Parent is already applied, so resolved profile doesn't extend anything
+pub fn resolve(name: &str, profile: Value, profiles: &HashMap<String, Value>) -> Result<Value> {
+    let mut resolved = resolve_chain(profile, profiles, &mut vec![name.to_string()])?;
+    strip_overrides(&mut resolved);
+    if let Value::Object(resolved) = &mut resolved {
+        resolved.remove("extends");
+    }
+    Ok(resolved)
+}
+
+fn resolve_chain(
+    profile: Value,
+    profiles: &HashMap<String, Value>,
+    chain: &mut Vec<String>,
+) -> Result<Value> {
+    let parent = match profile.get("extends") {
+        None | Some(Value::Null) => return Ok(profile),
+        Some(Value::String(parent)) => parent.clone(),
+        Some(_) => return Err(anyhow::anyhow!("Field 'extends' must be a string")),
+    };
+    if chain.contains(&parent) {
+        return Err(anyhow::anyhow!(
+            "Cyclic inheritance: {} -> {}",
+            chain.join(" -> "),
+            parent
+        ));
+    }
+    let parent_profile = match profiles.get(&parent) {
+        Some(parent_profile) => parent_profile.clone(),
+        None => read_template(&parent)
+            .with_context(|| format!("Can't load parent profile or template '{}'", parent))?,
+    };
+    chain.push(parent);
+    let mut resolved = resolve_chain(parent_profile, profiles, chain)?;
+    merge(&mut resolved, profile);
+    Ok(resolved)
+}
+
+fn read_template(name: &str) -> Result<Value> {
+    let path =
+        Path::new(TEMPLATES_DIR).join(RelativePath::new(format!("{}.json", name))?.to_path());
+    Ok(serde_json::from_reader(File::open(path)?)?)
+}
+
+// Lists are appended to parent ones, objects are merged, everything else is overridden.
+// Key with '!' suffix (e.g. "jvmArgs!") replaces parent value instead of merging.
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                if let Some(key) = key.strip_suffix('!') {
+                    base.insert(key.to_string(), value);
+                    continue;
+                }
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(overlay)) => base.extend(overlay),
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// Override keys of root profile or newly added values have nothing to replace
+fn strip_overrides(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            let keys: Vec<String> = object
+                .keys()
+                .filter(|key| key.ends_with('!'))
+                .cloned()
+                .collect();
+            for key in keys {
+                if let Some(override_value) = object.remove(&key) {
+                    object.insert(key.trim_end_matches('!').to_string(), override_value);
+                }
+            }
+            object.values_mut().for_each(strip_overrides);
+        }
+        Value::Array(array) => array.iter_mut().for_each(strip_overrides),
+        _ => {}
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use launcher_api::profile::PROFILE_SCHEMA_VERSION;
+use serde_json::{Map, Value};
+
+type Migration = fn(&mut Map<String, Value>);
+
+// Migration at index N upgrades profile from schema N to N + 1
+const MIGRATIONS: [Migration; PROFILE_SCHEMA_VERSION as usize] = [migrate_v0];
+
+pub fn get_schema_version(profile: &Value) -> Result<u32> {
+    match profile.get("schemaVersion") {
+        Some(version) => Ok(version
+            .as_u64()
+            .context("Field 'schemaVersion' must be a positive number")?
+            as u32),
+        None => Ok(0),
+    }
+}
+
+pub fn migrate(mut profile: Value) -> Result<Value> {
+    let version = get_schema_version(&profile)?;
+    if version > PROFILE_SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "Schema version {} is newer than supported {}, please update launch server",
+            version,
+            PROFILE_SCHEMA_VERSION
+        ));
+    }
+    let object = profile
+        .as_object_mut()
+        .context("Profile must be a JSON object")?;
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(object);
+        object.insert("schemaVersion".to_string(), Value::from(from as u32 + 1));
+    }
+    Ok(profile)
+}
+
+fn migrate_v0(profile: &mut Map<String, Value>) {
+    for field in &[
+        "classPath",
+        "updateVerify",
+        "updateExclusion",
+        "jvmArgs",
+        "clientArgs",
+    ] {
+        profile
+            .entry(field.to_string())
+            .or_insert_with(|| Value::Array(Vec::new()));
+    }
+    profile
+        .entry("serverName")
+        .or_insert_with(|| Value::from("localhost"));
+    profile
+        .entry("serverPort")
+        .or_insert_with(|| Value::from(25565));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_legacy_profile() {
+        let profile = migrate(serde_json::json!({
+            "name": "Test",
+            "jvmArgs": ["-Xmx2G"],
+            "serverPort": 25566
+        }))
+        .unwrap();
+        assert_eq!(
+            get_schema_version(&profile).unwrap(),
+            PROFILE_SCHEMA_VERSION
+        );
+        assert_eq!(profile["jvmArgs"], serde_json::json!(["-Xmx2G"]));
+        assert_eq!(profile["classPath"], serde_json::json!([]));
+        assert_eq!(profile["serverName"], "localhost");
+        assert_eq!(profile["serverPort"], 25566);
+    }
+
+    #[test]
+    fn current_profile_is_unchanged() {
+        let profile = serde_json::json!({
+            "schemaVersion": PROFILE_SCHEMA_VERSION,
+            "name": "Test"
+        });
+        assert_eq!(migrate(profile.clone()).unwrap(), profile);
+    }
+
+    #[test]
+    fn newer_or_invalid_schema_is_rejected() {
+        assert!(
+            migrate(serde_json::json!({ "schemaVersion": PROFILE_SCHEMA_VERSION + 1 })).is_err()
+        );
+        assert!(migrate(serde_json::json!({ "schemaVersion": "1" })).is_err());
+        assert!(migrate(serde_json::json!([])).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+
+fn is_trusted(trusted_proxies: &[IpNet], ip: &IpAddr) -> bool {
+    trusted_proxies.iter().any(|net| net.contains(ip))
+}
+
+// Entries may contain port: "1.2.3.4:5678" or "[::1]:5678"
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    value
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+pub fn get_client_ip(
+    trusted_proxies: &[IpNet],
+    peer: IpAddr,
+    real_ip: Option<&str>,
+    forwarded_for: Option<&str>,
+) -> IpAddr {
+    if !is_trusted(trusted_proxies, &peer) {
+        return peer;
+    }
+    if let Some(ip) = real_ip.and_then(parse_ip) {
+        return ip;
+    }
+    // Each proxy appends the address it got request from, so walk from the right
+    // until first address that isn't our proxy
+    let mut client = peer;
+    if let Some(forwarded_for) = forwarded_for {
+        for hop in forwarded_for.rsplit(',') {
+            match parse_ip(hop) {
+                Some(ip) => {
+                    client = ip;
+                    if !is_trusted(trusted_proxies, &ip) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted() -> Vec<IpNet> {
+        vec!["10.0.0.0/8".parse().unwrap(), "::1/128".parse().unwrap()]
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn untrusted_peer_headers_are_ignored() {
+        let peer = ip("203.0.113.7");
+        assert_eq!(
+            get_client_ip(&trusted(), peer, Some("1.1.1.1"), Some("2.2.2.2")),
+            peer
+        );
+    }
+
+    #[test]
+    fn real_ip_from_trusted_peer() {
+        assert_eq!(
+            get_client_ip(
+                &trusted(),
+                ip("10.0.0.1"),
+                Some(" 1.1.1.1 "),
+                Some("2.2.2.2")
+            ),
+            ip("1.1.1.1")
+        );
+    }
+
+    #[test]
+    fn forwarded_for_skips_trusted_hops() {
+        // Client spoofed the first entry, only hops added by our proxies are believed
+        assert_eq!(
+            get_client_ip(
+                &trusted(),
+                ip("10.0.0.1"),
+                None,
+                Some("6.6.6.6, 1.1.1.1, 10.0.0.3, 10.0.0.2")
+            ),
+            ip("1.1.1.1")
+        );
+        // All hops are trusted
+        assert_eq!(
+            get_client_ip(&trusted(), ip("10.0.0.1"), None, Some("10.0.0.3,10.0.0.2")),
+            ip("10.0.0.3")
+        );
+    }
+
+    #[test]
+    fn forwarded_for_with_ports() {
+        assert_eq!(
+            get_client_ip(
+                &trusted(),
+                ip("::1"),
+                None,
+                Some("1.1.1.1:5678, [::1]:8080")
+            ),
+            ip("1.1.1.1")
+        );
+        assert_eq!(
+            get_client_ip(&trusted(), ip("::1"), None, Some("[2001:db8::1]:443")),
+            ip("2001:db8::1")
+        );
+    }
+
+    #[test]
+    fn garbage_hop_stops_walk() {
+        assert_eq!(
+            get_client_ip(
+                &trusted(),
+                ip("10.0.0.1"),
+                None,
+                Some("1.1.1.1, unknown, 10.0.0.2")
+            ),
+            ip("10.0.0.2")
+        );
+        assert_eq!(
+            get_client_ip(&trusted(), ip("10.0.0.1"), Some("garbage"), Some("")),
+            ip("10.0.0.1")
+        );
+    }
+}
@@ -3,14 +3,14 @@ use std::sync::Arc;
 use anyhow::Result;
 use futures::{FutureExt, StreamExt};
 use launcher_api::message::{
-    AuthMessage, AuthResponse, ClientMessage, Error, JoinServerMessage, ProfileMessage,
-    ProfileResourcesMessage, ProfileResourcesResponse, ProfileResponse, ProfilesInfoMessage,
-    ProfilesInfoResponse, ServerMessage,
+    AuthMessage, AuthResponse, ClientMessage, ClientRequest, Error, ErrorKind, HandshakeMessage,
+    HandshakeResponse, JoinServerMessage, LogoutMessage, ProfileMessage, ProfileResourcesMessage,
+    ProfileResourcesResponse, ProfileResponse, ProfilesInfoMessage, ProfilesInfoResponse,
+    RefreshMessage, ServerMessage, ServerResponse, UploadTextureMessage, PROTOCOL_VERSION,
 };
-use launcher_api::validation::RemoteDirectory;
-use log::debug;
-use log::error;
-use rand::Rng;
+use launcher_api::validation::{HashAlgorithm, RemoteDirectory};
+use log::{debug, error, info};
+use serde_json::Value;
 use std::collections::HashMap;
 use std::hash::Hash;
 use tokio::macros::support::Future;
@@ -18,14 +18,18 @@ use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::{mpsc, RwLock};
 use warp::filters::ws::{Message, WebSocket};
 
-use crate::security::NativeVersion;
+use crate::security::{NativeVersion, ProfileOs};
+use crate::session;
+use crate::session::Session;
 use crate::LaunchServer;
 
 pub struct Client {
-    #[allow(unused)] // remove when ready ip limiter
     ip: String,
     access_token: Option<String>,
     username: Option<String>,
+    handshake: bool,
+    hash_algorithm: Option<HashAlgorithm>,
+    launcher_version: Option<String>,
 }
 
 impl Client {
@@ -34,6 +38,9 @@ impl Client {
             ip: ip.to_string(),
             access_token: None,
             username: None,
+            handshake: false,
+            hash_algorithm: None,
+            launcher_version: None,
         }
     }
 }
@@ -46,7 +53,7 @@ pub async fn ws_api(ws: WebSocket, server: Arc<RwLock<LaunchServer>>, ip: String
             error!("Websocket send error: {}", e);
         }
     }));
-    let mut client = Client::new(&ip);
+    let client = Arc::new(RwLock::new(Client::new(&ip)));
     while let Some(result) = ws_rx.next().await {
         let msg = match result {
             Ok(msg) => msg,
@@ -57,47 +64,145 @@ pub async fn ws_api(ws: WebSocket, server: Arc<RwLock<LaunchServer>>, ip: String
         };
         if let Ok(json) = msg.to_str() {
             debug!("Client message: {:?}", json.to_string());
-            if let Ok(message) = serde_json::from_str::<ClientMessage>(json) {
-                match message {
-                    ClientMessage::Auth(auth) => {
-                        auth.handle(tx.clone(), server.clone(), &mut client).await;
-                    }
-                    ClientMessage::JoinServer(join) => {
-                        join.handle(tx.clone(), server.clone(), &mut client).await;
-                    }
-                    ClientMessage::Profile(profile) => {
-                        profile
-                            .handle(tx.clone(), server.clone(), &mut client)
-                            .await;
-                    }
-                    ClientMessage::ProfileResources(resources) => {
-                        resources
-                            .handle(tx.clone(), server.clone(), &mut client)
-                            .await;
-                    }
-                    ClientMessage::ProfilesInfo(profiles_info) => {
-                        profiles_info
-                            .handle(tx.clone(), server.clone(), &mut client)
-                            .await;
+            let request = match serde_json::from_str::<ClientRequest>(json) {
+                Ok(request) => request,
+                Err(e) => {
+                    debug!("Can't parse client message: {}", e);
+                    let id = serde_json::from_str::<Value>(json)
+                        .ok()
+                        .and_then(|value| value.get("id").and_then(Value::as_u64));
+                    let error = Error::new(
+                        ErrorKind::OutdatedLauncher,
+                        "Unknown message, please update your launcher!",
+                    );
+                    match id {
+                        Some(id) => send(tx.clone(), id, async { Err(error.into()) }).await,
+                        // Launchers before handshake expect bare message without envelope
+                        None => send_legacy(&tx, ServerMessage::Error(error)),
                     }
+                    continue;
+                }
+            };
+            let id = request.id;
+            let handshake_done = client.read().await.handshake;
+            match request.message {
+                message @ ClientMessage::Handshake(_) => {
+                    handle_message(message, tx.clone(), id, server.clone(), client.clone()).await;
+                }
+                _ if !handshake_done => {
+                    send(tx.clone(), id, async {
+                        Err(Error::new(
+                            ErrorKind::OutdatedLauncher,
+                            "Handshake required, please update your launcher!",
+                        )
+                        .into())
+                    })
+                    .await;
+                }
+                message => {
+                    tokio::spawn(handle_message(
+                        message,
+                        tx.clone(),
+                        id,
+                        server.clone(),
+                        client.clone(),
+                    ));
                 }
             }
         }
     }
 }
 
+async fn handle_message(
+    message: ClientMessage,
+    tx: UnboundedSender<Result<Message, warp::Error>>,
+    id: u64,
+    server: Arc<RwLock<LaunchServer>>,
+    client: Arc<RwLock<Client>>,
+) {
+    match message {
+        ClientMessage::Handshake(handshake) => handshake.handle(tx, id, server, client).await,
+        ClientMessage::Auth(auth) => auth.handle(tx, id, server, client).await,
+        ClientMessage::Refresh(refresh) => refresh.handle(tx, id, server, client).await,
+        ClientMessage::Logout(logout) => logout.handle(tx, id, server, client).await,
+        ClientMessage::JoinServer(join) => join.handle(tx, id, server, client).await,
+        ClientMessage::Profile(profile) => profile.handle(tx, id, server, client).await,
+        ClientMessage::ProfileResources(resources) => {
+            resources.handle(tx, id, server, client).await
+        }
+        ClientMessage::ProfilesInfo(profiles_info) => {
+            profiles_info.handle(tx, id, server, client).await
+        }
+        ClientMessage::UploadTexture(upload) => upload.handle(tx, id, server, client).await,
+    }
+}
+
 async fn send(
     tx: UnboundedSender<Result<Message, warp::Error>>,
+    id: u64,
     f: impl Future<Output = Result<ServerMessage>>,
 ) {
     let message = match f.await {
         Ok(message) => message,
-        Err(e) => ServerMessage::Error(Error {
-            msg: format!("{}", e),
+        Err(e) => ServerMessage::Error(match e.downcast::<Error>() {
+            Ok(error) => error,
+            // Internal errors may contain hosts or queries, client gets only generic message
+            Err(e) => {
+                error!("Request {} failed: {:#}", id, e);
+                Error::new(ErrorKind::Internal, "Internal server error")
+            }
         }),
     };
-    tx.send(Ok(Message::text(serde_json::to_string(&message).unwrap())))
-        .expect("Can't send message to client");
+    let response = ServerResponse { id, message };
+    if tx
+        .send(Ok(Message::text(serde_json::to_string(&response).unwrap())))
+        .is_err()
+    {
+        debug!("Client disconnected before response {} was sent", id);
+    }
+}
+
+fn send_legacy(tx: &UnboundedSender<Result<Message, warp::Error>>, message: ServerMessage) {
+    if tx
+        .send(Ok(Message::text(serde_json::to_string(&message).unwrap())))
+        .is_err()
+    {
+        debug!("Client disconnected before response was sent");
+    }
+}
+
+fn provider_error(error: anyhow::Error) -> Error {
+    error!("Auth provider error: {:#}", error);
+    Error::new(
+        ErrorKind::ProviderUnavailable,
+        "Auth provider is unavailable, try again later",
+    )
+}
+
+// Provider still gets access token, external hasJoined implementations rely on it
+async fn start_session(
+    server: &LaunchServer,
+    client: &RwLock<Client>,
+    (access_token, session): (String, Session),
+) -> Result<ServerMessage> {
+    if let Err(e) = server
+        .config
+        .auth
+        .update_access_token(&session.uuid, &access_token)
+        .await
+    {
+        server.sessions.revoke(&access_token);
+        return Err(provider_error(e).into());
+    }
+    let mut client = client.write().await;
+    client.username = Some(session.username.clone());
+    client.access_token = Some(access_token.clone());
+    Ok(ServerMessage::Auth(AuthResponse {
+        uuid: session.uuid.to_string(),
+        access_token,
+        refresh_token: session.refresh_token,
+        expires_in: server.sessions.get_expires_in(),
+    }))
 }
 
 #[async_trait::async_trait]
@@ -105,8 +210,9 @@ pub trait Handle {
     async fn handle(
         &self,
         tx: UnboundedSender<Result<Message, warp::Error>>,
+        id: u64,
         server: Arc<RwLock<LaunchServer>>,
-        client: &mut Client,
+        client: Arc<RwLock<Client>>,
     );
 }
 
@@ -123,9 +229,58 @@ where
         .flatten()
     {
         Some(resource) => Ok(resource.to_owned()),
-        None => Err(anyhow::anyhow!(
-            "This profile resource doesn't exist or not synchronized!"
-        )),
+        None => Err(Error::new(
+            ErrorKind::ResourcesNotSynchronized,
+            "This profile resource doesn't exist or not synchronized!",
+        )
+        .into()),
+    }
+}
+
+#[async_trait::async_trait]
+impl Handle for HandshakeMessage {
+    async fn handle(
+        &self,
+        tx: UnboundedSender<Result<Message, warp::Error>>,
+        id: u64,
+        _server: Arc<RwLock<LaunchServer>>,
+        client: Arc<RwLock<Client>>,
+    ) {
+        send(tx, id, async {
+            if self.protocol_version < PROTOCOL_VERSION {
+                return Err(Error::new(
+                    ErrorKind::OutdatedLauncher,
+                    "Your launcher is outdated, please update it!",
+                )
+                .with_details(PROTOCOL_VERSION.to_string())
+                .into());
+            }
+            if self.protocol_version > PROTOCOL_VERSION {
+                return Err(Error::new(
+                    ErrorKind::OutdatedServer,
+                    "Launch server is outdated, please contact to administration!",
+                )
+                .with_details(PROTOCOL_VERSION.to_string())
+                .into());
+            }
+            let mut client = client.write().await;
+            info!(
+                "Launcher {} connected from {} ({:?})",
+                self.launcher_version, client.ip, self.os_type
+            );
+            client.handshake = true;
+            client.launcher_version = Some(self.launcher_version.clone());
+            client.hash_algorithm = if self.hash_algorithms.contains(&HashAlgorithm::Sha256) {
+                Some(HashAlgorithm::Sha256)
+            } else {
+                None
+            };
+            Ok(ServerMessage::Handshake(HandshakeResponse {
+                protocol_version: PROTOCOL_VERSION,
+                hash_algorithm: client.hash_algorithm,
+            }))
+        })
+        .await;
     }
 }
 
@@ -134,14 +289,22 @@ impl Handle for ProfileResourcesMessage {
     async fn handle(
         &self,
         tx: UnboundedSender<Result<Message, warp::Error>>,
+        id: u64,
         server: Arc<RwLock<LaunchServer>>,
-        _client: &mut Client,
+        client: Arc<RwLock<Client>>,
     ) {
         let server = &*server.read().await;
-        send(tx, async {
+        let hash_algorithm = client.read().await.hash_algorithm;
+        send(tx, id, async {
             match server.profiles.get(&self.profile) {
                 Some(profile) => {
-                    let libraries = get_resource(&server.security.libraries, &self.profile)?;
+                    let libraries = get_resource(
+                        &server.security.libraries,
+                        &ProfileOs {
+                            profile: self.profile.clone(),
+                            os_type: self.os_type.clone(),
+                        },
+                    )?;
                     let assets = get_resource(&server.security.assets, &profile.assets)?;
                     let natives = get_resource(
                         &server.security.natives,
@@ -153,15 +316,37 @@ impl Handle for ProfileResourcesMessage {
                     let jre = get_resource(&server.security.jres, &self.os_type)?;
                     let profile = get_resource(&server.security.profiles, &self.profile)?;
 
-                    Ok(ServerMessage::ProfileResources(ProfileResourcesResponse {
+                    let mut response = ProfileResourcesResponse {
+                        profile_name: self.profile.clone(),
+                        os_type: self.os_type.clone(),
                         profile,
                         libraries,
                         assets,
                         natives,
                         jre,
-                    }))
+                    };
+                    if hash_algorithm.is_none() {
+                        for directory in [
+                            &mut response.profile,
+                            &mut response.libraries,
+                            &mut response.assets,
+                            &mut response.natives,
+                            &mut response.jre,
+                        ]
+                        .iter_mut()
+                        {
+                            directory.values_mut().for_each(|file| file.digest = None);
+                        }
+                    }
+                    Ok(ServerMessage::ProfileResources(
+                        server.security.sign(&response)?,
+                    ))
                 }
-                None => Err(anyhow::anyhow!("This profile doesn't exist!")),
+                None => Err(
+                    Error::new(ErrorKind::ProfileNotFound, "This profile doesn't exist!")
+                        .with_details(self.profile.as_str())
+                        .into(),
+                ),
             }
         })
         .await;
@@ -173,16 +358,23 @@ impl Handle for ProfileMessage {
     async fn handle(
         &self,
         tx: UnboundedSender<Result<Message, warp::Error>>,
+        id: u64,
         server: Arc<RwLock<LaunchServer>>,
-        _client: &mut Client,
+        _client: Arc<RwLock<Client>>,
     ) {
         let server = server.read().await;
-        send(tx, async {
+        send(tx, id, async {
             match server.profiles.get(&self.profile) {
-                Some(profile) => Ok(ServerMessage::Profile(ProfileResponse {
-                    profile: profile.to_owned(),
-                })),
-                None => Err(anyhow::anyhow!("This profile doesn't exist!")),
+                Some(profile) => Ok(ServerMessage::Profile(server.security.sign(
+                    &ProfileResponse {
+                        profile: profile.to_owned(),
+                    },
+                )?)),
+                None => Err(
+                    Error::new(ErrorKind::ProfileNotFound, "This profile doesn't exist!")
+                        .with_details(self.profile.as_str())
+                        .into(),
+                ),
             }
         })
         .await;
@@ -194,11 +386,12 @@ impl Handle for ProfilesInfoMessage {
     async fn handle(
         &self,
         tx: UnboundedSender<Result<Message, warp::Error>>,
+        id: u64,
         server: Arc<RwLock<LaunchServer>>,
-        _client: &mut Client,
+        _client: Arc<RwLock<Client>>,
     ) {
         let server = server.read().await;
-        send(tx, async {
+        send(tx, id, async {
             Ok(ServerMessage::ProfilesInfo(ProfilesInfoResponse {
                 profiles_info: server.profiles_info.clone(),
             }))
@@ -212,41 +405,106 @@ impl Handle for AuthMessage {
     async fn handle(
         &self,
         tx: UnboundedSender<Result<Message, warp::Error>>,
+        id: u64,
         server: Arc<RwLock<LaunchServer>>,
-        client: &mut Client,
+        client: Arc<RwLock<Client>>,
     ) {
         let server = server.read().await;
-        //TODO ADD IP FOR LIMITERS
-        let ip = "".to_string();
-        send(tx, async {
-            let password = server.security.decrypt(&self.password)?;
-            let result = server.config.auth.auth(&self.login, &password, &ip).await?;
+        let (ip, launcher_version) = {
+            let client = client.read().await;
+            (client.ip.clone(), client.launcher_version.clone())
+        };
+        send(tx, id, async {
+            let attempt = server
+                .auth_limiter
+                .try_acquire(&ip, &self.login)
+                .map_err(|lockout| {
+                    let seconds = lockout.as_secs() + 1;
+                    Error::new(
+                        ErrorKind::RateLimited,
+                        format!("Too many login attempts, try again in {} seconds", seconds),
+                    )
+                    .with_details(seconds.to_string())
+                })?;
+            let password = server.security.decrypt(&self.password).map_err(|e| {
+                debug!("Can't decrypt password from {}: {}", ip, e);
+                Error::new(ErrorKind::BadRequest, "Can't decrypt password")
+            })?;
+            let result = server
+                .config
+                .auth
+                .auth(&self.login, &password, &ip)
+                .await
+                .map_err(provider_error)?;
             if result.message.is_none() {
-                let digest = {
-                    let mut rng = rand::thread_rng();
-                    md5::compute(format!(
-                        "{}{}{}",
-                        rng.gen_range(1000000000, 2147483647),
-                        rng.gen_range(1000000000, 2147483647),
-                        rng.gen_range(0, 9)
-                    ))
-                };
-                let access_token = format!("{:x}", digest);
-                let uuid = result.uuid.unwrap();
-                server
-                    .config
-                    .auth
-                    .update_access_token(&uuid, &access_token)
-                    .await?;
-                client.username = Some(self.login.clone());
-                client.access_token = Some(access_token.clone());
-                Ok(ServerMessage::Auth(AuthResponse {
-                    uuid: uuid.to_string(),
-                    access_token: access_token.to_string(),
-                }))
+                attempt.success();
+                let session = server.sessions.create(
+                    result.uuid.unwrap(),
+                    &self.login,
+                    &ip,
+                    launcher_version,
+                    self.remember_me,
+                    None,
+                );
+                start_session(&server, &client, session).await
             } else {
-                Err(anyhow::anyhow!("{}", result.message.unwrap()))
+                attempt.fail();
+                Err(Error::new(ErrorKind::AuthFailed, result.message.unwrap()).into())
+            }
+        })
+        .await;
+    }
+}
+
+#[async_trait::async_trait]
+impl Handle for RefreshMessage {
+    async fn handle(
+        &self,
+        tx: UnboundedSender<Result<Message, warp::Error>>,
+        id: u64,
+        server: Arc<RwLock<LaunchServer>>,
+        client: Arc<RwLock<Client>>,
+    ) {
+        let server = server.read().await;
+        let (ip, launcher_version) = {
+            let client = client.read().await;
+            (client.ip.clone(), client.launcher_version.clone())
+        };
+        send(tx, id, async {
+            let session = server
+                .sessions
+                .refresh(&self.refresh_token, &ip, launcher_version)
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidAccessToken,
+                        "Session expired, please log in again",
+                    )
+                })?;
+            start_session(&server, &client, session).await
+        })
+        .await;
+    }
+}
+
+#[async_trait::async_trait]
+impl Handle for LogoutMessage {
+    async fn handle(
+        &self,
+        tx: UnboundedSender<Result<Message, warp::Error>>,
+        id: u64,
+        server: Arc<RwLock<LaunchServer>>,
+        client: Arc<RwLock<Client>>,
+    ) {
+        let server = server.read().await;
+        send(tx, id, async {
+            let revoked = session::revoke(&server, &self.access_token).await;
+            let mut client = client.write().await;
+            if client.access_token.as_deref() == Some(self.access_token.as_str()) {
+                client.access_token = None;
+                client.username = None;
             }
+            revoked.map_err(provider_error)?;
+            Ok(ServerMessage::Empty)
         })
         .await;
     }
@@ -257,30 +515,62 @@ impl Handle for JoinServerMessage {
     async fn handle(
         &self,
         tx: UnboundedSender<Result<Message, warp::Error>>,
+        id: u64,
         server: Arc<RwLock<LaunchServer>>,
-        _client: &mut Client,
+        client: Arc<RwLock<Client>>,
     ) {
         let server = server.read().await;
-        send(tx, async {
-            let provide = &server.config.auth;
-            let entry = provide.get_entry(&self.selected_profile).await;
-            match entry {
-                Ok(e) => {
-                    if e.access_token.is_some() && e.access_token.unwrap().eq(&self.access_token) {
-                        provide
-                            .update_server_id(&self.selected_profile, &self.server_id)
-                            .await?;
-                        Ok(ServerMessage::Empty)
-                    } else {
-                        Ok(ServerMessage::Error(Error {
-                            msg: String::from("Access token error"),
-                        }))
-                    }
+        let ip = client.read().await.ip.clone();
+        send(tx, id, async {
+            match server.sessions.get(&self.access_token) {
+                Some(session) if session.uuid == self.selected_profile => {
+                    server
+                        .config
+                        .auth
+                        .update_server_id(&self.selected_profile, &self.server_id)
+                        .await
+                        .map_err(provider_error)?;
+                    server
+                        .sessions
+                        .join(self.selected_profile, &self.server_id, &ip);
+                    Ok(ServerMessage::Empty)
                 }
-                Err(error) => Ok(ServerMessage::Error(Error {
-                    msg: format!("{}", error),
-                })),
+                _ => Err(Error::new(ErrorKind::InvalidAccessToken, "Access token error").into()),
+            }
+        })
+        .await;
+    }
+}
+
+#[async_trait::async_trait]
+impl Handle for UploadTextureMessage {
+    async fn handle(
+        &self,
+        tx: UnboundedSender<Result<Message, warp::Error>>,
+        id: u64,
+        server: Arc<RwLock<LaunchServer>>,
+        _client: Arc<RwLock<Client>>,
+    ) {
+        let server = server.read().await;
+        send(tx, id, async {
+            let session = server
+                .sessions
+                .get(&self.access_token)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidAccessToken, "Access token error"))?;
+            if !server.textures.is_enabled() {
+                return Err(Error::new(ErrorKind::BadRequest, "Texture upload is disabled").into());
+            }
+            if !self.data.is_empty() {
+                server
+                    .textures
+                    .validate(self.kind, &self.data)
+                    .map_err(|e| Error::new(ErrorKind::BadRequest, e.to_string()))?;
             }
+            let textures = server.textures.clone();
+            let (uuid, kind, data, slim) = (session.uuid, self.kind, self.data.clone(), self.slim);
+            tokio::task::spawn_blocking(move || textures.upload(uuid, kind, &data, slim)).await??;
+            info!("{} uploaded new {:?}", session.username, self.kind);
+            Ok(ServerMessage::Empty)
         })
         .await;
     }
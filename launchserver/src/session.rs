@@ -0,0 +1,491 @@
+use anyhow::{Context, Result};
+use log::{error, info};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock as SyncRwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::config::{AuthProvider, SessionConfig};
+use crate::LaunchServer;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub uuid: Uuid,
+    pub username: String,
+    pub ip: String,
+    pub launcher_version: Option<String>,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    pub refresh_token: Option<String>,
+    pub refresh_expires_at: Option<u64>,
+    // Set by Yggdrasil clients
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_token: Option<String>,
+}
+
+impl Session {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at <= now && !self.can_refresh(now)
+    }
+
+    fn can_refresh(&self, now: u64) -> bool {
+        matches!(self.refresh_expires_at, Some(expires_at) if expires_at > now)
+    }
+
+    pub fn matches_client(&self, client_token: Option<&str>) -> bool {
+        match client_token {
+            Some(client_token) => self.client_token.as_deref() == Some(client_token),
+            None => true,
+        }
+    }
+}
+
+// Issued on JoinServer, consumed by hasJoined
+struct JoinTicket {
+    server_id: String,
+    ip: String,
+    created: Instant,
+}
+
+pub struct SessionManager {
+    config: SessionConfig,
+    // Keyed by access token
+    sessions: SyncRwLock<HashMap<String, Session>>,
+    joins: SyncRwLock<HashMap<Uuid, JoinTicket>>,
+    // Sessions file is written by start_saver, not on every change
+    dirty: AtomicBool,
+}
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+fn get_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+fn normalize_ip(ip: &str) -> Option<IpAddr> {
+    match ip.parse().ok()? {
+        IpAddr::V6(ip) => Some(ip.to_ipv4().map(IpAddr::V4).unwrap_or(IpAddr::V6(ip))),
+        ip => Some(ip),
+    }
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl SessionManager {
+    pub fn new(config: &SessionConfig) -> Self {
+        let sessions = SessionManager::load(&config.file).unwrap_or_else(|e| {
+            error!("Can't load sessions, all users must log in again: {:#}", e);
+            HashMap::new()
+        });
+        SessionManager {
+            config: config.clone(),
+            sessions: SyncRwLock::new(sessions),
+            joins: SyncRwLock::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    fn load(file: &str) -> Result<HashMap<String, Session>> {
+        let path = Path::new(file);
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        let sessions: HashMap<String, Session> =
+            serde_json::from_str(&fs::read_to_string(path)?)
+                .with_context(|| format!("Can't parse sessions file {}", file))?;
+        let now = get_time();
+        Ok(sessions
+            .into_iter()
+            .filter(|(_, session)| !session.is_expired(now))
+            .collect())
+    }
+
+    fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    // Serialized sessions if they were changed since last call
+    fn take_changes(&self) -> Option<String> {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return None;
+        }
+        let sessions = self.sessions.read().expect("Sessions are poisoned");
+        match serde_json::to_string(&*sessions) {
+            Ok(content) => Some(content),
+            Err(e) => {
+                error!("Can't serialize sessions: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn get_expires_in(&self) -> u64 {
+        self.config.access_token_ttl
+    }
+
+    pub fn create(
+        &self,
+        uuid: Uuid,
+        username: &str,
+        ip: &str,
+        launcher_version: Option<String>,
+        remember: bool,
+        client_token: Option<String>,
+    ) -> (String, Session) {
+        let now = get_time();
+        let access_token = generate_token();
+        let session = Session {
+            uuid,
+            username: username.to_string(),
+            ip: ip.to_string(),
+            launcher_version,
+            issued_at: now,
+            expires_at: now + self.config.access_token_ttl,
+            refresh_token: if remember {
+                Some(generate_token())
+            } else {
+                None
+            },
+            refresh_expires_at: if remember {
+                Some(now + self.config.refresh_token_ttl)
+            } else {
+                None
+            },
+            client_token,
+        };
+        let mut sessions = self.sessions.write().expect("Sessions are poisoned");
+        sessions.insert(access_token.clone(), session.clone());
+        self.mark_dirty();
+        (access_token, session)
+    }
+
+    pub fn get(&self, access_token: &str) -> Option<Session> {
+        self.sessions
+            .read()
+            .expect("Sessions are poisoned")
+            .get(access_token)
+            .filter(|session| session.expires_at > get_time())
+            .cloned()
+    }
+
+    // Refresh token can be used only once, new session gets new pair of tokens
+    pub fn refresh(
+        &self,
+        refresh_token: &str,
+        ip: &str,
+        launcher_version: Option<String>,
+    ) -> Option<(String, Session)> {
+        let now = get_time();
+        let session = {
+            let mut sessions = self.sessions.write().expect("Sessions are poisoned");
+            let access_token = sessions
+                .iter()
+                .find(|(_, session)| {
+                    session.refresh_token.as_deref() == Some(refresh_token)
+                        && session.can_refresh(now)
+                })
+                .map(|(access_token, _)| access_token.clone())?;
+            sessions.remove(&access_token)?
+        };
+        Some(self.create(
+            session.uuid,
+            &session.username,
+            ip,
+            launcher_version,
+            true,
+            session.client_token,
+        ))
+    }
+
+    // Yggdrasil refresh, access token itself may already be expired
+    pub fn renew(
+        &self,
+        access_token: &str,
+        client_token: Option<&str>,
+        ip: &str,
+    ) -> Option<(String, Session)> {
+        let now = get_time();
+        let session = {
+            let mut sessions = self.sessions.write().expect("Sessions are poisoned");
+            let session = sessions.get(access_token)?;
+            if !session.matches_client(client_token)
+                || (session.expires_at <= now && !session.can_refresh(now))
+            {
+                return None;
+            }
+            sessions.remove(access_token)?
+        };
+        let remember = session.refresh_token.is_some();
+        Some(self.create(
+            session.uuid,
+            &session.username,
+            ip,
+            session.launcher_version,
+            remember,
+            session.client_token,
+        ))
+    }
+
+    pub fn revoke(&self, access_token: &str) -> Option<Session> {
+        let mut sessions = self.sessions.write().expect("Sessions are poisoned");
+        let session = sessions.remove(access_token)?;
+        self.mark_dirty();
+        Some(session)
+    }
+
+    pub fn revoke_user(&self, uuid: &Uuid) -> usize {
+        let mut sessions = self.sessions.write().expect("Sessions are poisoned");
+        let count = sessions.len();
+        sessions.retain(|_, session| &session.uuid != uuid);
+        let removed = count - sessions.len();
+        if removed > 0 {
+            self.mark_dirty();
+        }
+        removed
+    }
+
+    pub fn join(&self, uuid: Uuid, server_id: &str, ip: &str) {
+        self.joins.write().expect("Joins are poisoned").insert(
+            uuid,
+            JoinTicket {
+                server_id: server_id.to_string(),
+                ip: ip.to_string(),
+                created: Instant::now(),
+            },
+        );
+    }
+
+    // Ticket is removed on success, so server id can't be replayed
+    pub fn check_join(&self, uuid: &Uuid, server_id: &str, ip: Option<&str>) -> bool {
+        let ttl = Duration::from_secs(self.config.join_ttl);
+        let mut joins = self.joins.write().expect("Joins are poisoned");
+        let valid = match joins.get(uuid) {
+            Some(ticket) => {
+                let ip_matches = match ip.map(normalize_ip) {
+                    Some(ip) => ip.is_some() && ip == normalize_ip(&ticket.ip),
+                    None => true,
+                };
+                ticket.server_id == server_id && ticket.created.elapsed() < ttl && ip_matches
+            }
+            None => false,
+        };
+        if valid {
+            joins.remove(uuid);
+        }
+        valid
+    }
+
+    pub fn cleanup(&self) -> usize {
+        let now = get_time();
+        let mut sessions = self.sessions.write().expect("Sessions are poisoned");
+        let count = sessions.len();
+        sessions.retain(|_, session| !session.is_expired(now));
+        let removed = count - sessions.len();
+        if removed > 0 {
+            self.mark_dirty();
+        }
+        let ttl = Duration::from_secs(self.config.join_ttl);
+        self.joins
+            .write()
+            .expect("Joins are poisoned")
+            .retain(|_, ticket| ticket.created.elapsed() < ttl);
+        removed
+    }
+}
+
+pub async fn start_saver(data: Arc<RwLock<LaunchServer>>) {
+    loop {
+        tokio::time::delay_for(SAVE_INTERVAL).await;
+        let (file, content) = {
+            let server = data.read().await;
+            match server.sessions.take_changes() {
+                Some(content) => (server.sessions.config.file.clone(), content),
+                None => continue,
+            }
+        };
+        let saved = tokio::task::spawn_blocking(move || match write_private(&file, &content) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Can't save sessions to {}: {}", file, e);
+                false
+            }
+        })
+        .await;
+        if !matches!(saved, Ok(true)) {
+            data.read().await.sessions.mark_dirty();
+        }
+    }
+}
+
+// Tokens in the file are enough to impersonate users, so it's readable only by owner
+fn write_private(file: &str, content: &str) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(file)?;
+    // Mode is applied only on creation, files saved by older versions are fixed here
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(content.as_bytes())
+}
+
+pub async fn start_cleanup(data: Arc<RwLock<LaunchServer>>) {
+    let interval = data.read().await.config.sessions.cleanup_interval.max(1);
+    loop {
+        tokio::time::delay_for(Duration::from_secs(interval)).await;
+        let removed = data.read().await.sessions.cleanup();
+        if removed > 0 {
+            info!("Removed {} expired sessions", removed);
+        }
+    }
+}
+
+// Provider keeps only the latest access token, it's replaced if revoked session owns it
+pub async fn revoke(server: &LaunchServer, access_token: &str) -> Result<Option<Session>> {
+    let session = match server.sessions.revoke(access_token) {
+        Some(session) => session,
+        None => return Ok(None),
+    };
+    let auth = &server.config.auth;
+    let entry = auth.get_entry(&session.uuid).await?;
+    if entry.access_token.as_deref() == Some(access_token) {
+        rotate_provider_token(auth, &session.uuid).await?;
+    }
+    Ok(Some(session))
+}
+
+pub async fn rotate_provider_token(auth: &AuthProvider, uuid: &Uuid) -> Result<()> {
+    auth.update_access_token(uuid, &generate_token()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> SessionManager {
+        SessionManager::new(&SessionConfig {
+            file: "missing_test_sessions.json".to_string(),
+            ..SessionConfig::default()
+        })
+    }
+
+    #[test]
+    fn join_is_single_use() {
+        let sessions = manager();
+        let uuid = Uuid::new_v4();
+        sessions.join(uuid, "server", "1.2.3.4");
+        assert!(!sessions.check_join(&uuid, "other", None));
+        assert!(sessions.check_join(&uuid, "server", None));
+        assert!(!sessions.check_join(&uuid, "server", None));
+    }
+
+    #[test]
+    fn join_expires() {
+        let sessions = manager();
+        let uuid = Uuid::new_v4();
+        sessions.join(uuid, "server", "1.2.3.4");
+        let ttl = Duration::from_secs(sessions.config.join_ttl);
+        if let Some(ticket) = sessions.joins.write().unwrap().get_mut(&uuid) {
+            ticket.created -= ttl;
+        }
+        assert!(!sessions.check_join(&uuid, "server", None));
+    }
+
+    #[test]
+    fn join_matches_ip() {
+        let sessions = manager();
+        let uuid = Uuid::new_v4();
+        sessions.join(uuid, "server", "::ffff:1.2.3.4");
+        assert!(!sessions.check_join(&uuid, "server", Some("1.2.3.5")));
+        assert!(!sessions.check_join(&uuid, "server", Some("garbage")));
+        assert!(sessions.check_join(&uuid, "server", Some("1.2.3.4")));
+        sessions.join(uuid, "server", "2001:db8::1");
+        assert!(sessions.check_join(&uuid, "server", Some("2001:db8:0::1")));
+    }
+
+    #[test]
+    fn refresh_rotates_tokens() {
+        let sessions = manager();
+        let (access_token, session) =
+            sessions.create(Uuid::new_v4(), "Player", "1.2.3.4", None, true, None);
+        let refresh_token = session.refresh_token.unwrap();
+        let (new_access_token, new_session) =
+            sessions.refresh(&refresh_token, "1.2.3.5", None).unwrap();
+        assert_ne!(new_access_token, access_token);
+        assert_ne!(
+            new_session.refresh_token.as_deref(),
+            Some(refresh_token.as_str())
+        );
+        assert_eq!(new_session.ip, "1.2.3.5");
+        assert!(sessions.get(&access_token).is_none());
+        assert!(sessions.refresh(&refresh_token, "1.2.3.4", None).is_none());
+        assert!(sessions.get(&new_access_token).is_some());
+    }
+
+    #[test]
+    fn refresh_requires_remember() {
+        let sessions = manager();
+        let (_, session) = sessions.create(Uuid::new_v4(), "Player", "1.2.3.4", None, false, None);
+        assert!(session.refresh_token.is_none());
+    }
+
+    #[test]
+    fn renew_rotates_access_token() {
+        let sessions = manager();
+        let client_token = Some("client".to_string());
+        let (access_token, _) = sessions.create(
+            Uuid::new_v4(),
+            "Player",
+            "1.2.3.4",
+            None,
+            true,
+            client_token,
+        );
+        assert!(sessions
+            .renew(&access_token, Some("other"), "1.2.3.4")
+            .is_none());
+        // Expired access token is renewed while refresh token is valid
+        if let Some(session) = sessions.sessions.write().unwrap().get_mut(&access_token) {
+            session.expires_at = get_time();
+        }
+        let (new_access_token, session) = sessions
+            .renew(&access_token, Some("client"), "1.2.3.4")
+            .unwrap();
+        assert_ne!(new_access_token, access_token);
+        assert_eq!(session.client_token.as_deref(), Some("client"));
+        assert!(sessions.renew(&access_token, None, "1.2.3.4").is_none());
+        assert!(sessions.get(&new_access_token).is_some());
+    }
+
+    #[test]
+    fn renew_of_expired_session() {
+        let sessions = manager();
+        let (access_token, _) =
+            sessions.create(Uuid::new_v4(), "Player", "1.2.3.4", None, false, None);
+        if let Some(session) = sessions.sessions.write().unwrap().get_mut(&access_token) {
+            session.expires_at = get_time();
+        }
+        assert!(sessions.get(&access_token).is_none());
+        assert!(sessions.renew(&access_token, None, "1.2.3.4").is_none());
+    }
+}
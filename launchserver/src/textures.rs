@@ -0,0 +1,316 @@
+use anyhow::{Context, Result};
+use byteorder::{BigEndian, ByteOrder};
+use launcher_api::message::TextureKind;
+use log::error;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+use crate::config::TextureStorageConfig;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StoredTexture {
+    pub hash: String,
+    #[serde(default)]
+    pub slim: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct UserTextures {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skin: Option<StoredTexture>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cape: Option<StoredTexture>,
+}
+
+impl UserTextures {
+    fn get_mut(&mut self, kind: TextureKind) -> &mut Option<StoredTexture> {
+        match kind {
+            TextureKind::Skin => &mut self.skin,
+            TextureKind::Cape => &mut self.cape,
+        }
+    }
+
+    fn contains(&self, hash: &str) -> bool {
+        [&self.skin, &self.cape]
+            .iter()
+            .any(|texture| matches!(texture, Some(texture) if texture.hash == hash))
+    }
+}
+
+// Files are named by sha256 of content, index.json maps users to their textures
+pub struct TextureStorage {
+    config: TextureStorageConfig,
+    index: RwLock<HashMap<Uuid, UserTextures>>,
+}
+
+// Whole chunk structure is checked, so clients get only well-formed files
+fn get_size(data: &[u8]) -> Result<(u32, u32)> {
+    let invalid = || anyhow::anyhow!("Texture is not a PNG image");
+    if data.len() < 8 || data[..8] != PNG_SIGNATURE {
+        return Err(invalid());
+    }
+    let mut size = None;
+    let mut offset = 8;
+    loop {
+        if data.len() < offset + 12 {
+            return Err(invalid());
+        }
+        let length = BigEndian::read_u32(&data[offset..offset + 4]) as usize;
+        if length > data.len() - offset - 12 {
+            return Err(invalid());
+        }
+        let end = offset + 8 + length;
+        // Crc covers chunk type and data
+        if crc32fast::hash(&data[offset + 4..end]) != BigEndian::read_u32(&data[end..end + 4]) {
+            return Err(anyhow::anyhow!("Texture has corrupted PNG chunk"));
+        }
+        let chunk = &data[offset + 4..offset + 8];
+        let body = &data[offset + 8..end];
+        match (chunk, size) {
+            // IHDR must be the first chunk
+            (b"IHDR", None) if length == 13 => {
+                size = Some((
+                    BigEndian::read_u32(&body[0..4]),
+                    BigEndian::read_u32(&body[4..8]),
+                ))
+            }
+            (_, None) => return Err(invalid()),
+            (b"IEND", Some(size)) => return Ok(size),
+            (b"IHDR", Some(_)) => return Err(invalid()),
+            _ => {}
+        }
+        offset = end + 4;
+    }
+}
+
+impl TextureStorage {
+    pub fn new(config: &TextureStorageConfig) -> Self {
+        let index = if config.enabled {
+            TextureStorage::load(&config.directory).unwrap_or_else(|e| {
+                error!("Can't load texture index: {:#}", e);
+                HashMap::new()
+            })
+        } else {
+            HashMap::new()
+        };
+        TextureStorage {
+            config: config.clone(),
+            index: RwLock::new(index),
+        }
+    }
+
+    fn load(directory: &str) -> Result<HashMap<Uuid, UserTextures>> {
+        let path = Path::new(directory).join("index.json");
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        serde_json::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Can't parse texture index {:?}", path))
+    }
+
+    fn save(&self, index: &HashMap<Uuid, UserTextures>) -> Result<()> {
+        fs::write(
+            Path::new(&self.config.directory).join("index.json"),
+            serde_json::to_string(index)?,
+        )?;
+        Ok(())
+    }
+
+    fn get_path(&self, hash: &str) -> PathBuf {
+        Path::new(&self.config.directory).join(format!("{}.png", hash))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn get_url(&self, hash: &str) -> String {
+        format!("{}/{}.png", self.config.url.trim_end_matches('/'), hash)
+    }
+
+    pub fn get(&self, uuid: &Uuid) -> UserTextures {
+        if !self.config.enabled {
+            return UserTextures::default();
+        }
+        self.index
+            .read()
+            .expect("Texture index is poisoned")
+            .get(uuid)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Skins are 64x64 or legacy 64x32, capes are 64x32, both can be scaled up to max scale
+    pub fn validate(&self, kind: TextureKind, data: &[u8]) -> Result<()> {
+        if data.len() > self.config.max_size {
+            return Err(anyhow::anyhow!(
+                "Texture is too large, max size is {} bytes",
+                self.config.max_size
+            ));
+        }
+        let (width, height) = get_size(data)?;
+        let scale = width / 64;
+        let valid = width % 64 == 0
+            && scale >= 1
+            && scale <= self.config.max_scale
+            && match kind {
+                TextureKind::Skin => height == width || height == width / 2,
+                TextureKind::Cape => height == width / 2,
+            };
+        if valid {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "Invalid {:?} size {}x{}",
+                kind,
+                width,
+                height
+            ))
+        }
+    }
+
+    // Data must be validated before, empty data removes texture
+    // Index lock is held while files are changed, so cleanup can't remove a file being uploaded
+    pub fn upload(&self, uuid: Uuid, kind: TextureKind, data: &[u8], slim: bool) -> Result<()> {
+        let mut index = self.index.write().expect("Texture index is poisoned");
+        fs::create_dir_all(&self.config.directory)?;
+        let texture = if data.is_empty() {
+            None
+        } else {
+            let hash = format!("{:x}", Sha256::digest(data));
+            let path = self.get_path(&hash);
+            if !path.exists() {
+                fs::write(&path, data)?;
+            }
+            Some(StoredTexture {
+                hash,
+                slim: slim && kind == TextureKind::Skin,
+            })
+        };
+        let textures = index.entry(uuid).or_default();
+        let previous = std::mem::replace(textures.get_mut(kind), texture);
+        if textures.skin.is_none() && textures.cape.is_none() {
+            index.remove(&uuid);
+        }
+        self.save(&index)?;
+        // Same file can be shared by several users
+        if let Some(previous) = previous {
+            if !index
+                .values()
+                .any(|textures| textures.contains(&previous.hash))
+            {
+                if let Err(e) = fs::remove_file(self.get_path(&previous.hash)) {
+                    error!("Can't remove texture {}: {}", previous.hash, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(data: &mut Vec<u8>, kind: &[u8], body: &[u8]) {
+        data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        let start = data.len();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        let crc = crc32fast::hash(&data[start..]);
+        data.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        chunk(&mut data, b"IHDR", &header);
+        chunk(
+            &mut data,
+            b"IDAT",
+            &[0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01],
+        );
+        chunk(&mut data, b"IEND", &[]);
+        data
+    }
+
+    fn storage(max_scale: u32) -> TextureStorage {
+        TextureStorage::new(&TextureStorageConfig {
+            enabled: false,
+            directory: "textures".to_string(),
+            url: "http://127.0.0.1:8080/textures".to_string(),
+            max_size: 1024,
+            max_scale,
+        })
+    }
+
+    #[test]
+    fn png_size() {
+        assert_eq!(get_size(&png(64, 32)).unwrap(), (64, 32));
+        assert!(get_size(b"GIF89a").is_err());
+        let mut data = png(64, 64);
+        data[12..16].copy_from_slice(b"IDAT");
+        assert!(get_size(&data).is_err());
+        assert!(get_size(&png(64, 64)[..20]).is_err());
+    }
+
+    #[test]
+    fn png_chunks() {
+        // Size is changed without updating crc
+        let mut data = png(64, 64);
+        data[19] = 128;
+        assert!(get_size(&data).is_err());
+        // Missing IEND
+        let data = png(64, 64);
+        assert!(get_size(&data[..data.len() - 12]).is_err());
+        // IHDR of wrong length
+        let mut data = PNG_SIGNATURE.to_vec();
+        chunk(&mut data, b"IHDR", &[0; 12]);
+        chunk(&mut data, b"IEND", &[]);
+        assert!(get_size(&data).is_err());
+        // Chunk length past the end of file
+        let mut data = png(64, 64);
+        data[33..37].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(get_size(&data).is_err());
+    }
+
+    #[test]
+    fn skin_sizes() {
+        let storage = storage(2);
+        for (width, height) in [(64, 64), (64, 32), (128, 128), (128, 64)].iter() {
+            assert!(storage
+                .validate(TextureKind::Skin, &png(*width, *height))
+                .is_ok());
+        }
+        for (width, height) in [(256, 256), (64, 48), (32, 32), (0, 0), (96, 96)].iter() {
+            assert!(storage
+                .validate(TextureKind::Skin, &png(*width, *height))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn cape_sizes() {
+        let storage = storage(1);
+        assert!(storage.validate(TextureKind::Cape, &png(64, 32)).is_ok());
+        assert!(storage.validate(TextureKind::Cape, &png(64, 64)).is_err());
+        assert!(storage.validate(TextureKind::Cape, &png(128, 64)).is_err());
+    }
+
+    #[test]
+    fn max_size() {
+        let mut data = png(64, 64);
+        data.resize(1025, 0);
+        assert!(storage(1).validate(TextureKind::Skin, &data).is_err());
+    }
+}